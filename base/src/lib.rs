@@ -1,239 +1,71 @@
-use std::{
-    collections::VecDeque,
-    fs::File,
-    io::{BufWriter, Write},
-};
-
-#[derive(Debug)]
-enum Opcode {
-    Plus,
-    Minus,
-    Rigth,
-    Left,
-    Comma,
-    Dot,
-    OpenBracket,
-    CloseBracket,
-}
-
-fn parse(character: char) -> Option<Opcode> {
-    match character {
-        '+' => Some(Opcode::Plus),
-        '-' => Some(Opcode::Minus),
-        '>' => Some(Opcode::Rigth),
-        '<' => Some(Opcode::Left),
-        ',' => Some(Opcode::Comma),
-        '.' => Some(Opcode::Dot),
-        '[' => Some(Opcode::OpenBracket),
-        ']' => Some(Opcode::CloseBracket),
-        _ => None,
-    }
-}
-
-#[derive(Debug)]
-enum Expression {
-    Plus,
-    Minus,
-    Rigth,
-    Left,
-    Comma,
-    Dot,
-
-    Conditional(Ast),
-}
+pub mod runtime;
 
-#[derive(Debug)]
-struct Ast(Vec<Expression>);
-
-fn generate_ast_vec(tokens: &mut VecDeque<Opcode>) -> Result<Ast, String> {
-    let mut ast = vec![];
-
-    while let Some(opcode) = tokens.pop_front() {
-        let expr = match opcode {
-            Opcode::Plus => Expression::Plus,
-            Opcode::Minus => Expression::Minus,
-            Opcode::Rigth => Expression::Rigth,
-            Opcode::Left => Expression::Left,
-            Opcode::Comma => Expression::Comma,
-            Opcode::Dot => Expression::Dot,
-            Opcode::OpenBracket => {
-                let inner = generate_ast_vec(tokens)?;
-                match tokens.pop_front() {
-                    Some(Opcode::CloseBracket) => Expression::Conditional(inner),
-                    _ => return Err("missing closing bracket".into()),
-                }
-            }
-            Opcode::CloseBracket => {
-                tokens.push_front(Opcode::CloseBracket);
-                return Ok(Ast(ast));
-            }
-        };
-
-        ast.push(expr);
-    }
+use std::{io::Write, path::Path, process::Command};
 
-    Ok(Ast(ast))
-}
-
-fn generate_ast(tokens: &mut VecDeque<Opcode>) -> Result<Ast, String> {
-    let block = generate_ast_vec(tokens)?;
-
-    if tokens.is_empty() {
-        Ok(block)
-    } else {
-        Err("Extra character in input".into())
-    }
-}
+use frontend::{backend::run_tool, Ast, Backend, Bounds, Error, Expression, Options};
+use runtime::{
+    current_cell, generate_end, generate_header, generate_input, generate_output, register,
+};
 
-fn generate_header(f: &mut impl Write) -> std::io::Result<()> {
-    write!(
-        f,
-        r#"global _start
-
-section .data
-  memory: times 1000 db 0
-  buffer: db 0
-
-  hexbuff: times 90 db " "
-  idxbuff: db 0xA, " idx: --  "
-  hex: db "0123456789abcdef"
-
-section .text
-"#
-    )?;
-
-    if cfg!(debug_assertions) {
-        write!(
-            f,
-            r#"
-dump:
-  mov rax, r14             ; print index
-
-  mov rcx, 0xf0
-  and rcx, rax
-  sar rcx, 4
-  mov rcx, [hex + rcx]
-  mov [idxbuff + 7], cl 
-
-  mov rcx, 0x0f
-  and rcx, rax
-  mov rcx, [hex + rcx]
-  mov [idxbuff + 8], cl 
-
-  mov rax, 1
-  mov rdi, 1
-  mov rdx, 11
-  mov rsi, idxbuff 
-  syscall
-
-  mov r13, 0
-dumploop:
-  mov rax, [memory + r13]
-
-  mov rcx, 0xf0
-  and rcx, rax
-  sar rcx, 4
-  mov rcx, [hex + rcx]
-  mov [hexbuff + r13 * 3 + 0], cl 
-
-  mov rcx, 0x0f
-  and rcx, rax
-  mov rcx, [hex + rcx]
-  mov [hexbuff + r13 * 3 + 1], cl 
-
-  inc r13 
-  cmp r13, 30
-  jnz dumploop
-
-  mov byte [hexbuff + 89], 0xA
-
-  mov rax, 1
-  mov rdi, 1
-  mov rdx, 91
-  mov rsi, hexbuff 
-  syscall
-
-  ret
-"#
-        )?;
+/// Moves the head by one cell to the right or to the left (`v` is 1 or -1),
+/// checking it against the tape as `options.bounds` says.
+fn generate_step(v: i32, options: &Options, f: &mut impl Write) -> std::io::Result<()> {
+    let size = options.tape_size;
+    let (cell, register) = (
+        current_cell(options.cell_width),
+        register(options.cell_width),
+    );
+
+    writeln!(f, "  mov {cell}, {register}")?;
+
+    match options.bounds {
+        Bounds::Wrap if v > 0 => {
+            writeln!(f, "  inc r14")?;
+            writeln!(f, "  xor rax, rax")?;
+            writeln!(f, "  cmp r14, {size}")?;
+            writeln!(f, "  cmove r14, rax")?;
+        }
+        Bounds::Wrap => {
+            writeln!(f, "  mov rax, {}", size - 1)?;
+            writeln!(f, "  dec r14")?;
+            writeln!(f, "  cmovs r14, rax")?;
+        }
+        _ if v > 0 => writeln!(f, "  inc r14")?,
+        _ => writeln!(f, "  dec r14")?,
     }
 
-    write!(
-        f,
-        r#"
-_start:
-  mov r15b, 0              ; current cell
-  mov r14, 0               ; index
-"#
-    )?;
-
-    Ok(())
-}
-
-fn generate_end(f: &mut impl Write) -> std::io::Result<()> {
-    writeln!(f, "  mov byte [memory + r14], r15b")?;
-
-    if cfg!(debug_assertions) {
-        writeln!(f, "  call dump")?;
+    if options.bounds == Bounds::Trap {
+        // a negative index is above the size as unsigned
+        writeln!(f, "  cmp r14, {size}")?;
+        writeln!(f, "  jae bounds_error")?;
     }
 
-    writeln!(
-        f,
-        r#"
-  mov rax, 60
-  xor rdi, rdi
-  syscall"#
-    )?;
+    writeln!(f, "  mov {register}, {cell}")?;
 
     Ok(())
 }
 
-fn generate_code_ast(ast: Ast, f: &mut impl Write, call_stack: &mut u32) -> std::io::Result<()> {
+fn generate_code_ast(
+    ast: Ast,
+    options: &Options,
+    f: &mut impl Write,
+    call_stack: &mut u32,
+) -> std::io::Result<()> {
     for statement in ast.0 {
-        match statement {
-            Expression::Plus => writeln!(f, "  inc r15b"),
-            Expression::Minus => writeln!(f, "  dec r15b"),
-            Expression::Rigth => {
-                writeln!(f, "  mov byte [memory + r14], r15b")?;
-                writeln!(f, "  inc r14")?;
-                writeln!(f, "  mov byte r15b, [memory + r14]")?;
-                Ok(())
-            }
-            Expression::Left => {
-                writeln!(f, "  mov byte [memory + r14], r15b")?;
-                writeln!(f, "  dec r14")?;
-                writeln!(f, "  mov byte r15b, [memory + r14]")?;
-                Ok(())
-            }
-            Expression::Comma => {
-                writeln!(f, "  xor rax, rax")?;
-                writeln!(f, "  xor rdi, rdi")?;
-                writeln!(f, "  mov rdx, 1")?;
-                writeln!(f, "  mov rsi, buffer")?;
-                writeln!(f, "  syscall")?;
-                writeln!(f, "  mov byte r15b, [buffer]")?;
-                Ok(())
-            }
-            Expression::Dot => {
-                writeln!(f, "  mov byte [buffer], r15b")?;
-                // writeln!(f, "  cmp r15b, 10 ; replace line feed with space")?;
-                // writeln!(f, "  jnz continue")?;
-                // writeln!(f, "  mov byte [buffer], 32")?;
-                // writeln!(f, "continue:")?;
-                writeln!(f, "  mov rax, 1")?;
-                writeln!(f, "  mov rdi, 1")?;
-                writeln!(f, "  mov rdx, 1")?;
-                writeln!(f, "  mov rsi, buffer")?;
-                writeln!(f, "  syscall")?;
-                Ok(())
-            }
-            Expression::Conditional(inner) => {
+        match statement.node {
+            Expression::Plus => writeln!(f, "  inc {}", register(options.cell_width)),
+            Expression::Minus => writeln!(f, "  dec {}", register(options.cell_width)),
+            Expression::Right => generate_step(1, options, f),
+            Expression::Left => generate_step(-1, options, f),
+            Expression::Comma => generate_input(options, f),
+            Expression::Dot => generate_output(options, f),
+            Expression::Conditional(inner, _) => {
                 let current = *call_stack;
                 *call_stack += 1;
                 writeln!(f, "call_{current}:")?;
-                writeln!(f, "  cmp r15b, 0")?;
+                writeln!(f, "  cmp {}, 0", register(options.cell_width))?;
                 writeln!(f, "  jz end_{current}")?;
-                generate_code_ast(inner, f, call_stack)?;
+                generate_code_ast(inner, options, f, call_stack)?;
                 writeln!(f, "  jmp call_{current}")?;
                 writeln!(f, "end_{current}:")?;
 
@@ -245,29 +77,173 @@ fn generate_code_ast(ast: Ast, f: &mut impl Write, call_stack: &mut u32) -> std:
     Ok(())
 }
 
-fn generate_code(ast: Ast, f: &mut impl Write) -> std::io::Result<()> {
+fn generate_code(ast: Ast, options: &Options, f: &mut impl Write) -> std::io::Result<()> {
     let mut call_stack = 0;
 
-    generate_header(f)?;
-    generate_code_ast(ast, f, &mut call_stack)?;
-    generate_end(f)?;
+    generate_header(options, f)?;
+    generate_code_ast(ast, options, f, &mut call_stack)?;
+    generate_end(options, f)?;
 
     Ok(())
 }
 
-pub fn compile(input: String) -> Result<(), String> {
-    let mut opcodes = input
-        .chars()
-        .filter(|c| !c.is_whitespace())
-        .filter_map(parse)
-        .collect::<VecDeque<_>>();
+/// Writes the NASM assembly of `ast` into `f`.
+pub fn compile(ast: Ast, options: &Options, f: &mut impl Write) -> Result<(), Error> {
+    generate_code(ast, options, f).map_err(Error::io("assembly output"))?;
+    f.flush().map_err(Error::io("assembly output"))?;
 
-    let ast = generate_ast(&mut opcodes)?;
+    Ok(())
+}
 
-    let file = File::create("code.s").unwrap();
-    let mut f = BufWriter::new(file);
-    generate_code(ast, &mut f).unwrap();
-    f.flush().unwrap();
+pub fn compile_to_string(ast: Ast, options: &Options) -> Result<String, Error> {
+    let mut out = vec![];
+    compile(ast, options, &mut out)?;
 
-    Ok(())
+    Ok(String::from_utf8(out).expect("generated assembly is ascii"))
+}
+
+pub struct BaseBackend;
+
+impl Backend for BaseBackend {
+    fn name(&self) -> &'static str {
+        "base"
+    }
+
+    fn extension(&self) -> &'static str {
+        "s"
+    }
+
+    /// The Ast is translated as is, the optimization level is ignored.
+    fn emit(&self, ast: Ast, options: &Options, mut f: &mut dyn Write) -> Result<(), Error> {
+        compile(ast, options, &mut f)
+    }
+
+    fn assemble(&self, source: &Path, object: &Path) -> Result<(), Error> {
+        run_tool(
+            Command::new("nasm")
+                .args(["-g", "-f", "elf64", "-o"])
+                .arg(object)
+                .arg(source),
+        )
+    }
+
+    fn link(&self, object: &Path, executable: &Path) -> Result<(), Error> {
+        run_tool(Command::new("ld").arg("-o").arg(executable).arg(object))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use frontend::{parse_source, Bounds, CellWidth, Options};
+
+    use crate::compile_to_string;
+
+    fn assembly(program: &str, options: &Options) -> String {
+        compile_to_string(parse_source(program).unwrap(), options).unwrap()
+    }
+
+    #[test]
+    fn growing_tape_is_mapped() {
+        let options = Options {
+            bounds: Bounds::Grow,
+            ..Options::default()
+        };
+        let asm = assembly(">", &options);
+
+        assert!(!asm.contains("memory: resb"));
+        assert!(asm.contains("  mov rbx, rax             ; tape"));
+        // running past the end faults and grows the tape, nothing is checked
+        assert!(asm.contains("  inc r14\n  mov r15b, byte [rbx + r14]"));
+        assert!(!asm.contains("bounds_error"));
+    }
+
+    #[test]
+    fn output_is_flushed_before_reading_and_at_exit() {
+        let asm = assembly(".,", &Options::default());
+
+        assert!(asm.contains("  jb input_byte\n  call flush\ninput_refill:"));
+        // debug builds dump the tape between the flush and the exit
+        let exit = asm
+            .rfind("  mov rax, 60\n  xor rdi, rdi\n  syscall")
+            .unwrap();
+        let mut end = asm[..exit].trim_end().lines().rev().take(2);
+        assert!(end.any(|line| line == "  call flush"));
+
+        let options = Options {
+            unbuffered: true,
+            ..Options::default()
+        };
+        assert!(!assembly(".,", &options).contains("flush"));
+    }
+
+    #[test]
+    fn bounds_modes() {
+        let step = |bounds, program| {
+            let options = Options {
+                bounds,
+                tape_size: 100,
+                ..Options::default()
+            };
+            assembly(program, &options)
+        };
+
+        let unchecked = step(Bounds::Unchecked, "><");
+        assert!(unchecked.contains("  inc r14\n  mov r15b, byte [rbx + r14]\n"));
+        assert!(unchecked.contains("  dec r14\n  mov r15b, byte [rbx + r14]\n"));
+        assert!(!unchecked.contains("bounds_error"));
+
+        let trap = step(Bounds::Trap, "><");
+        assert!(trap.contains("  inc r14\n  cmp r14, 100\n  jae bounds_error\n"));
+        assert!(trap.contains("  dec r14\n  cmp r14, 100\n  jae bounds_error\n"));
+        assert!(trap.contains("bounds_error:"));
+
+        let wrap = step(Bounds::Wrap, "><");
+        assert!(wrap.contains("  inc r14\n  xor rax, rax\n  cmp r14, 100\n  cmove r14, rax\n"));
+        assert!(wrap.contains("  mov rax, 99\n  dec r14\n  cmovs r14, rax\n"));
+        assert!(!wrap.contains("bounds_error"));
+    }
+
+    #[test]
+    fn head_starts_at_the_origin() {
+        let options = Options {
+            bounds: Bounds::Trap,
+            tape_size: 10,
+            tape_origin: 5,
+            ..Options::default()
+        };
+        let asm = assembly("<", &options);
+
+        // the tape is the same, the cells on the left of the origin are
+        // reached from it
+        assert!(asm.contains("  memory: resb 10\n"));
+        assert!(asm.contains("  mov r14, 5               ; index\n"));
+        assert!(asm.contains("  dec r14\n  cmp r14, 10\n  jae bounds_error\n"));
+        assert!(asm.contains("  sub r12, 5\n"));
+    }
+
+    #[test]
+    fn cell_widths() {
+        let widths = [
+            (CellWidth::Bits8, "r15b", "byte [rbx + r14]", 100),
+            (CellWidth::Bits16, "r15w", "word [rbx + r14 * 2]", 200),
+            (CellWidth::Bits32, "r15d", "dword [rbx + r14 * 4]", 400),
+            (CellWidth::Bits64, "r15", "qword [rbx + r14 * 8]", 800),
+        ];
+
+        for (cell_width, register, cell, bytes) in widths {
+            let options = Options {
+                cell_width,
+                tape_size: 100,
+                ..Options::default()
+            };
+            let asm = assembly("+[->-<].", &options);
+
+            assert!(asm.contains(&format!("  memory: resb {bytes}\n")));
+            assert!(asm.contains(&format!("  inc {register}\ncall_0:\n  cmp {register}, 0\n")));
+            assert!(asm.contains(&format!("  mov {cell}, {register}\n  inc r14\n")));
+            assert!(asm.contains(&format!("  mov {register}, {cell}\n  dec {register}\n")));
+            // only the low byte is written
+            assert!(asm.contains("  mov [outbuff + rax], r15b\n"));
+        }
+    }
 }
@@ -0,0 +1,644 @@
+//! Assembly shared by the native backends, `base` and `ir::codegen`: the
+//! routines the generated programs call and the code around them.
+
+use std::io::Write;
+
+use frontend::{Bounds, CellWidth, Eof, Options};
+
+/// Register caching the current cell.
+pub fn register(width: CellWidth) -> &'static str {
+    match width {
+        CellWidth::Bits8 => "r15b",
+        CellWidth::Bits16 => "r15w",
+        CellWidth::Bits32 => "r15d",
+        CellWidth::Bits64 => "r15",
+    }
+}
+
+/// Memory operand of the cell under the head.
+pub fn current_cell(width: CellWidth) -> String {
+    let size = match width {
+        CellWidth::Bits8 => "byte",
+        CellWidth::Bits16 => "word",
+        CellWidth::Bits32 => "dword",
+        CellWidth::Bits64 => "qword",
+    };
+
+    match width.bytes() {
+        1 => format!("{size} [rbx + r14]"),
+        n => format!("{size} [rbx + r14 * {n}]"),
+    }
+}
+
+/// Address space reserved for a growing tape, only the part in use is backed
+/// by memory.
+const TAPE_RESERVE: usize = 1 << 36;
+
+/// Writes the runtime of a growing tape and the start of the program mapping
+/// it.
+///
+/// The whole reserve is mapped without access and the first pages are made
+/// writable. An access past them faults on the next page, and the SIGSEGV
+/// handler doubles the writable part before resuming the program. Faults
+/// anywhere else restore the default action, so that they kill the program
+/// when the access is retried.
+fn generate_growing_tape(options: &Options, f: &mut impl Write) -> std::io::Result<()> {
+    let committed = (options.tape_size * options.cell_width.bytes()).div_ceil(4096) * 4096;
+
+    write!(
+        f,
+        r#"
+section .data
+  tapebase: dq 0
+  tapecommitted: dq {committed}
+  segvaction: dq segv_handler, 0x04000004, segv_restorer, 0
+  segvdefault: dq 0, 0, 0, 0
+  tapemsg: db "error: cannot map the tape", 0xA
+  tapemsglen: equ $ - tapemsg
+
+section .text
+segv_handler:              ; grow the tape when the fault is past its end
+  mov rax, [rsi + 16]      ; faulting address
+  sub rax, [tapebase]
+  mov rcx, [tapecommitted]
+  cmp rax, rcx
+  jb segv_default
+  mov rdx, {reserve}
+  cmp rax, rdx
+  jae segv_default
+segv_double:
+  shl rcx, 1
+  cmp rax, rcx
+  jae segv_double
+  cmp rcx, rdx
+  cmova rcx, rdx
+
+  mov rdi, [tapebase]      ; make the new part writable
+  add rdi, [tapecommitted]
+  mov rsi, rcx
+  sub rsi, [tapecommitted]
+  mov [tapecommitted], rcx
+  mov rdx, 3
+  mov rax, 10
+  syscall
+  test rax, rax
+  jnz segv_default
+  ret
+
+segv_default:              ; the retried access kills the program
+  mov rax, 13
+  mov rdi, 11
+  mov rsi, segvdefault
+  xor rdx, rdx
+  mov r10, 8
+  syscall
+  ret
+
+segv_restorer:
+  mov rax, 15
+  syscall
+
+tape_error:
+  mov rax, 1
+  mov rdi, 2
+  mov rsi, tapemsg
+  mov rdx, tapemsglen
+  syscall
+
+  mov rax, 60
+  mov rdi, 1
+  syscall
+
+_start:
+  mov rax, 9               ; reserve the tape without access
+  xor rdi, rdi
+  mov rsi, {reserve}
+  xor rdx, rdx
+  mov r10, 0x4022          ; MAP_PRIVATE | MAP_ANONYMOUS | MAP_NORESERVE
+  mov r8, -1
+  xor r9, r9
+  syscall
+  cmp rax, -4096
+  ja tape_error
+  mov rbx, rax             ; tape
+  mov [tapebase], rax
+
+  mov rdi, rax             ; make its first pages writable
+  mov rsi, {committed}
+  mov rdx, 3
+  mov rax, 10
+  syscall
+  test rax, rax
+  jnz tape_error
+
+  mov rax, 13              ; install the SIGSEGV handler
+  mov rdi, 11
+  mov rsi, segvaction
+  xor rdx, rdx
+  mov r10, 8
+  syscall
+"#,
+        reserve = TAPE_RESERVE
+    )
+}
+
+/// Size of the buffer output goes through unless `options.unbuffered` is
+/// set.
+const OUTPUT_BUFFER: usize = 8192;
+
+/// Writes the routines buffering the output: `output` appends the low byte
+/// of the cell and flushes once the buffer is full, `flush` writes out the
+/// buffer, retrying partial and interrupted writes.
+fn generate_output_buffer(f: &mut impl Write) -> std::io::Result<()> {
+    write!(
+        f,
+        r#"
+section .bss
+  outbuff: resb {size}
+
+section .data
+  outlen: dq 0
+
+section .text
+output:                    ; append the cell to the output buffer
+  mov rax, [outlen]
+  mov [outbuff + rax], r15b
+  inc rax
+  mov [outlen], rax
+  cmp rax, {size}
+  je flush
+  ret
+
+flush:                     ; write out the output buffer
+  xor r8, r8               ; bytes written
+flush_loop:
+  cmp r8, [outlen]
+  jae flush_done
+  mov rax, 1
+  mov rdi, 1
+  lea rsi, [outbuff + r8]
+  mov rdx, [outlen]
+  sub rdx, r8
+  syscall
+  cmp rax, -4              ; EINTR
+  je flush_loop
+  test rax, rax
+  jle flush_done           ; the output is lost, there is nobody to tell
+  add r8, rax
+  jmp flush_loop
+flush_done:
+  mov qword [outlen], 0
+  ret
+"#,
+        size = OUTPUT_BUFFER
+    )
+}
+
+/// Writes the low byte of the cell, through the output buffer unless
+/// `options.unbuffered` is set.
+pub fn generate_output(options: &Options, f: &mut impl Write) -> std::io::Result<()> {
+    if !options.unbuffered {
+        return writeln!(f, "  call output");
+    }
+
+    writeln!(f, "  mov byte [buffer], r15b")?;
+    writeln!(f, "  mov rax, 1")?;
+    writeln!(f, "  mov rdi, 1")?;
+    writeln!(f, "  mov rdx, 1")?;
+    writeln!(f, "  mov rsi, buffer")?;
+    writeln!(f, "  syscall")?;
+
+    Ok(())
+}
+
+/// Size of the chunks input is read in.
+const INPUT_BUFFER: usize = 8192;
+
+/// Writes the `input` routine, which returns the next input byte in rcx with
+/// rax set to 1, or rax set to 0 once the input is exhausted. The buffer is
+/// refilled with whatever a read returns, retrying interrupted reads.
+fn generate_input_buffer(options: &Options, f: &mut impl Write) -> std::io::Result<()> {
+    write!(
+        f,
+        r#"
+section .bss
+  inbuff: resb {size}
+
+section .data
+  inpos: dq 0
+  inlen: dq 0
+
+section .text
+input:                     ; next input byte in rcx, rax is 0 at the end
+  mov rax, [inpos]
+  cmp rax, [inlen]
+  jb input_byte{flush}
+input_refill:
+  xor rax, rax
+  xor rdi, rdi
+  mov rsi, inbuff
+  mov rdx, {size}
+  syscall
+  cmp rax, -4              ; EINTR
+  je input_refill
+  test rax, rax
+  jle input_end            ; end of the input, or an error
+  mov [inlen], rax
+  xor rax, rax
+input_byte:
+  movzx ecx, byte [inbuff + rax]
+  inc rax
+  mov [inpos], rax
+  mov rax, 1
+  ret
+input_end:
+  xor rax, rax
+  mov [inpos], rax
+  mov [inlen], rax
+  ret
+"#,
+        size = INPUT_BUFFER,
+        // what the program wrote so far may be a prompt for the input it is
+        // about to wait for
+        flush = if options.unbuffered {
+            ""
+        } else {
+            "\n  call flush"
+        }
+    )
+}
+
+/// Reads a byte into the cell, or stores the value `options.eof` asks for
+/// once the input is exhausted.
+pub fn generate_input(options: &Options, f: &mut impl Write) -> std::io::Result<()> {
+    writeln!(f, "  call input")?;
+
+    // a byte read fills the whole cell, r15 is written as a whole since
+    // narrower cells only look at its low part
+    match options.eof {
+        Eof::Zero => writeln!(f, "  xor edx, edx")?,
+        Eof::MinusOne => writeln!(f, "  mov rdx, -1")?,
+        Eof::Unchanged => writeln!(f, "  mov rdx, r15")?,
+    }
+    writeln!(f, "  cmp rax, 1")?;
+    writeln!(f, "  cmovne rcx, rdx")?;
+    writeln!(f, "  mov r15, rcx")?;
+
+    Ok(())
+}
+
+/// Writes the `bounds_error` routine trapping checked steps jump to: it
+/// prints the position of the head and exits with 1, after flushing the
+/// output.
+fn generate_bounds_error(options: &Options, f: &mut impl Write) -> std::io::Result<()> {
+    write!(
+        f,
+        r#"
+section .data
+  boundsmsg: db "error: tape head out of bounds at cell "
+  boundsmsglen: equ $ - boundsmsg
+  boundsbuff: times 24 db 0
+
+section .text
+bounds_error:              ; print the head position and exit with 1{flush}
+  mov r12, r14
+  sub r12, {origin}
+  mov rax, r12
+  mov rsi, boundsbuff + 23
+  mov byte [rsi], 0xA
+  mov rcx, 10
+  test rax, rax
+  jns bounds_digits
+  neg rax
+bounds_digits:
+  dec rsi
+  xor rdx, rdx
+  div rcx
+  add dl, "0"
+  mov [rsi], dl
+  test rax, rax
+  jnz bounds_digits
+
+  test r12, r12
+  jns bounds_print
+  dec rsi
+  mov byte [rsi], "-"
+bounds_print:
+  mov r13, rsi
+
+  mov rax, 1
+  mov rdi, 2
+  mov rsi, boundsmsg
+  mov rdx, boundsmsglen
+  syscall
+
+  mov rax, 1
+  mov rdi, 2
+  mov rsi, r13
+  mov rdx, boundsbuff + 24
+  sub rdx, r13
+  syscall
+
+  mov rax, 60
+  mov rdi, 1
+  syscall
+"#,
+        origin = options.tape_origin,
+        flush = if options.unbuffered {
+            ""
+        } else {
+            "\n  call flush"
+        }
+    )
+}
+
+/// Writes the start of a program: the tape, the routines it calls and the
+/// entry point setting up the registers.
+pub fn generate_header(options: &Options, f: &mut impl Write) -> std::io::Result<()> {
+    writeln!(f, "global _start")?;
+
+    // a growing tape is mapped at startup instead
+    if options.bounds != Bounds::Grow {
+        let bytes = options.tape_size * options.cell_width.bytes();
+        write!(f, "\nsection .bss\n  memory: resb {bytes}\n")?;
+    }
+
+    write!(
+        f,
+        r#"
+section .data
+  buffer: db 0
+
+  hexbuff: times 90 db " "
+  idxbuff: db 0xA, " idx: --  "
+  hex: db "0123456789abcdef"
+
+section .text
+"#
+    )?;
+
+    if cfg!(debug_assertions) {
+        write!(
+            f,
+            r#"
+dump:
+  mov rax, r14             ; print index, from the start
+  sub rax, {origin}
+
+  mov rcx, 0xf0
+  and rcx, rax
+  sar rcx, 4
+  mov rcx, [hex + rcx]
+  mov [idxbuff + 7], cl 
+
+  mov rcx, 0x0f
+  and rcx, rax
+  mov rcx, [hex + rcx]
+  mov [idxbuff + 8], cl 
+
+  mov rax, 1
+  mov rdi, 1
+  mov rdx, 11
+  mov rsi, idxbuff 
+  syscall
+
+  mov r13, 0
+dumploop:
+  mov rax, [rbx + r13 * {bytes} + {offset}]
+
+  mov rcx, 0xf0
+  and rcx, rax
+  sar rcx, 4
+  mov rcx, [hex + rcx]
+  mov [hexbuff + r13 * 3 + 0], cl 
+
+  mov rcx, 0x0f
+  and rcx, rax
+  mov rcx, [hex + rcx]
+  mov [hexbuff + r13 * 3 + 1], cl 
+
+  inc r13 
+  cmp r13, 30
+  jnz dumploop
+
+  mov byte [hexbuff + 89], 0xA
+
+  mov rax, 1
+  mov rdi, 1
+  mov rdx, 91
+  mov rsi, hexbuff 
+  syscall
+
+  ret
+"#,
+            origin = options.tape_origin,
+            bytes = options.cell_width.bytes(),
+            offset = options.tape_origin * options.cell_width.bytes()
+        )?;
+    }
+
+    if !options.unbuffered {
+        generate_output_buffer(f)?;
+    }
+
+    generate_input_buffer(options, f)?;
+
+    if options.bounds == Bounds::Trap {
+        generate_bounds_error(options, f)?;
+    }
+
+    if options.bounds == Bounds::Grow {
+        generate_growing_tape(options, f)?;
+    } else {
+        write!(
+            f,
+            r#"
+_start:
+  mov rbx, memory          ; tape
+"#
+        )?;
+    }
+
+    write!(
+        f,
+        r#"  mov r15, 0               ; current cell
+  mov r14, {:<16}; index
+"#,
+        options.tape_origin
+    )?;
+
+    Ok(())
+}
+
+/// Writes the end of a program, storing the cell and flushing the output
+/// before exiting with 0.
+pub fn generate_end(options: &Options, f: &mut impl Write) -> std::io::Result<()> {
+    let width = options.cell_width;
+    writeln!(f, "  mov {}, {}", current_cell(width), register(width))?;
+
+    if !options.unbuffered {
+        writeln!(f, "  call flush")?;
+    }
+
+    if cfg!(debug_assertions) {
+        writeln!(f, "  call dump")?;
+    }
+
+    writeln!(
+        f,
+        r#"
+  mov rax, 60
+  xor rdi, rdi
+  syscall"#
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use frontend::{Bounds, CellWidth, Eof, Options};
+
+    use super::{
+        current_cell, generate_bounds_error, generate_growing_tape, generate_header,
+        generate_input, generate_input_buffer, generate_output, generate_output_buffer, register,
+    };
+
+    fn assembly(write: impl FnOnce(&mut Vec<u8>) -> std::io::Result<()>) -> String {
+        let mut out = vec![];
+        write(&mut out).unwrap();
+
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn cells_by_width() {
+        let cells = [
+            (CellWidth::Bits8, "r15b", "byte [rbx + r14]"),
+            (CellWidth::Bits16, "r15w", "word [rbx + r14 * 2]"),
+            (CellWidth::Bits32, "r15d", "dword [rbx + r14 * 4]"),
+            (CellWidth::Bits64, "r15", "qword [rbx + r14 * 8]"),
+        ];
+
+        for (width, reg, cell) in cells {
+            assert_eq!(register(width), reg);
+            assert_eq!(current_cell(width), cell);
+        }
+    }
+
+    #[test]
+    fn growing_tape_commits_whole_pages() {
+        let options = Options {
+            bounds: Bounds::Grow,
+            tape_size: 3000,
+            cell_width: CellWidth::Bits16,
+            ..Options::default()
+        };
+        let asm = assembly(|f| generate_growing_tape(&options, f));
+
+        assert!(asm.contains("tapecommitted: dq 8192\n"));
+        // the whole reserve is mapped without access, then the tape made
+        // writable and the handler installed
+        assert!(asm.contains("  mov rsi, 68719476736\n  xor rdx, rdx\n  mov r10, 0x4022"));
+        assert!(asm.contains("  mov rsi, 8192\n  mov rdx, 3\n  mov rax, 10\n  syscall"));
+        assert!(asm.contains("  mov rax, 13              ; install the SIGSEGV handler"));
+        assert!(asm.contains("segv_handler:"));
+        assert!(asm.ends_with("  syscall\n"));
+    }
+
+    #[test]
+    fn output_goes_through_the_buffer() {
+        let buffered = assembly(|f| generate_output(&Options::default(), f));
+        assert_eq!(buffered, "  call output\n");
+
+        let options = Options {
+            unbuffered: true,
+            ..Options::default()
+        };
+        let unbuffered = assembly(|f| generate_output(&options, f));
+        assert!(unbuffered.starts_with("  mov byte [buffer], r15b\n"));
+        assert!(unbuffered.ends_with("  syscall\n"));
+
+        let routines = assembly(generate_output_buffer);
+        assert!(routines.contains("outbuff: resb 8192"));
+        // a full buffer is flushed, interrupted writes are retried
+        assert!(routines.contains("  cmp rax, 8192\n  je flush\n"));
+        assert!(routines.contains("  cmp rax, -4              ; EINTR\n  je flush_loop\n"));
+    }
+
+    #[test]
+    fn end_of_input() {
+        let modes = [
+            (Eof::Zero, "  xor edx, edx\n"),
+            (Eof::MinusOne, "  mov rdx, -1\n"),
+            (Eof::Unchanged, "  mov rdx, r15\n"),
+        ];
+
+        for (eof, value) in modes {
+            let options = Options {
+                eof,
+                ..Options::default()
+            };
+            let asm = assembly(|f| generate_input(&options, f));
+
+            // the value replaces the byte unless one was read
+            let expected =
+                format!("  call input\n{value}  cmp rax, 1\n  cmovne rcx, rdx\n  mov r15, rcx\n");
+            assert_eq!(asm, expected, "{eof:?}");
+        }
+    }
+
+    #[test]
+    fn input_is_refilled() {
+        let asm = assembly(|f| generate_input_buffer(&Options::default(), f));
+
+        assert!(asm.contains("inbuff: resb 8192"));
+        // bytes left in the buffer are handed out before reading more
+        assert!(asm.contains("  cmp rax, [inlen]\n  jb input_byte\n  call flush\ninput_refill:"));
+        assert!(asm.contains(
+            "  mov rdx, 8192\n  syscall\n  cmp rax, -4              ; EINTR\n  je input_refill\n"
+        ));
+        // a short read fills the buffer only as far as it went
+        assert!(asm.contains(
+            "  jle input_end            ; end of the input, or an error\n  mov [inlen], rax\n"
+        ));
+        assert!(asm
+            .contains("input_end:\n  xor rax, rax\n  mov [inpos], rax\n  mov [inlen], rax\n  ret"));
+
+        let options = Options {
+            unbuffered: true,
+            ..Options::default()
+        };
+        let asm = assembly(|f| generate_input_buffer(&options, f));
+        assert!(asm.contains("  jb input_byte\ninput_refill:"));
+    }
+
+    #[test]
+    fn bounds_error_shows_the_cell() {
+        let options = Options {
+            bounds: Bounds::Trap,
+            tape_origin: 7,
+            ..Options::default()
+        };
+        let asm = assembly(|f| generate_bounds_error(&options, f));
+
+        // the cell is counted from the origin, the output written so far is
+        // shown before the error
+        assert!(asm.contains("exit with 1\n  call flush\n  mov r12, r14\n  sub r12, 7\n"));
+        assert!(asm.contains("  mov byte [rsi], \"-\"\n"));
+        assert!(asm.ends_with("  mov rax, 60\n  mov rdi, 1\n  syscall\n"));
+
+        let header = assembly(|f| generate_header(&options, f));
+        assert!(header.contains("bounds_error:"));
+        assert!(header.ends_with("  mov r14, 7               ; index\n"));
+
+        for bounds in [Bounds::Unchecked, Bounds::Wrap, Bounds::Grow] {
+            let options = Options {
+                bounds,
+                ..options.clone()
+            };
+            let header = assembly(|f| generate_header(&options, f));
+            assert!(!header.contains("bounds_error"), "{bounds:?}");
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use std::{
+    io::{Read, Write},
+    path::Path,
+    process::{Command, Output},
+    str::FromStr,
+};
+
+use crate::{error::Error, parser::Ast};
+
+/// Which optimization passes run over the IR before code generation.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OptLevel {
+    #[default]
+    O0,
+    O1,
+    O2,
+    O3,
+    Os,
+}
+
+impl FromStr for OptLevel {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0" => Ok(OptLevel::O0),
+            "1" => Ok(OptLevel::O1),
+            "2" => Ok(OptLevel::O2),
+            "3" => Ok(OptLevel::O3),
+            "s" => Ok(OptLevel::Os),
+            _ => Err(Error::Usage(format!("unsupported optimization: \"-O{s}\""))),
+        }
+    }
+}
+
+/// What happens when the head moves outside of the tape.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Bounds {
+    /// Nothing is checked, moving outside of the tape is undefined behaviour.
+    #[default]
+    Unchecked,
+    /// The program stops with an error showing the position of the head.
+    Trap,
+    /// The tape is circular, the head wraps around its ends.
+    Wrap,
+    /// The tape grows to the right as the head reaches its end, the tape size
+    /// is only its initial size.
+    Grow,
+}
+
+impl FromStr for Bounds {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unchecked" => Ok(Bounds::Unchecked),
+            "trap" => Ok(Bounds::Trap),
+            "wrap" => Ok(Bounds::Wrap),
+            "grow" => Ok(Bounds::Grow),
+            _ => Err(Error::Usage(format!(
+                "unknown bounds mode {s:?}, expected one of: unchecked, trap, wrap, grow"
+            ))),
+        }
+    }
+}
+
+/// Width of the tape cells, arithmetic on them wraps around.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CellWidth {
+    #[default]
+    Bits8,
+    Bits16,
+    Bits32,
+    Bits64,
+}
+
+impl CellWidth {
+    pub fn bits(self) -> u32 {
+        match self {
+            CellWidth::Bits8 => 8,
+            CellWidth::Bits16 => 16,
+            CellWidth::Bits32 => 32,
+            CellWidth::Bits64 => 64,
+        }
+    }
+
+    pub fn bytes(self) -> usize {
+        self.bits() as usize / 8
+    }
+
+    /// Wraps `value` to the range of a cell, read as a signed number.
+    pub fn wrap(self, value: i64) -> i64 {
+        let shift = 64 - self.bits();
+        (value << shift) >> shift
+    }
+}
+
+impl FromStr for CellWidth {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "8" => Ok(CellWidth::Bits8),
+            "16" => Ok(CellWidth::Bits16),
+            "32" => Ok(CellWidth::Bits32),
+            "64" => Ok(CellWidth::Bits64),
+            _ => Err(Error::Usage(format!(
+                "unknown cell width {s:?}, expected one of: 8, 16, 32, 64"
+            ))),
+        }
+    }
+}
+
+/// What `,` stores in the cell once the input is exhausted.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Eof {
+    Zero,
+    MinusOne,
+    #[default]
+    Unchanged,
+}
+
+impl FromStr for Eof {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "zero" => Ok(Eof::Zero),
+            "minus-one" => Ok(Eof::MinusOne),
+            "unchanged" => Ok(Eof::Unchanged),
+            _ => Err(Error::Usage(format!(
+                "unknown eof behaviour {s:?}, expected one of: zero, minus-one, unchanged"
+            ))),
+        }
+    }
+}
+
+/// Settings shared by every backend.
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub opt_level: OptLevel,
+    /// Number of cells on the tape.
+    pub tape_size: usize,
+    /// Cell the head starts on, the cells on its left are reached with `<`.
+    pub tape_origin: usize,
+    pub bounds: Bounds,
+    pub cell_width: CellWidth,
+    pub eof: Eof,
+    /// Every `.` writes its byte at once instead of going through a buffer.
+    pub unbuffered: bool,
+    /// Cells are integers of any size instead of `cell_width` bits, only
+    /// interpreters support it.
+    pub unbounded: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            opt_level: OptLevel::default(),
+            tape_size: 30000,
+            tape_origin: 0,
+            bounds: Bounds::default(),
+            cell_width: CellWidth::default(),
+            eof: Eof::default(),
+            unbuffered: false,
+            unbounded: false,
+        }
+    }
+}
+
+/// Code generator turning an `Ast` into an executable, through an
+/// intermediate artifact (assembly, LLVM IR...) and an object file, or
+/// interpreter running it in process.
+pub trait Backend {
+    /// Name used to select the backend from the command line.
+    fn name(&self) -> &'static str;
+
+    /// Extension of the artifact written by `emit`.
+    fn extension(&self) -> &'static str;
+
+    fn emit(&self, ast: Ast, options: &Options, f: &mut dyn Write) -> Result<(), Error>;
+
+    /// Writes the assembly of `ast`, the default suits backends whose
+    /// artifact already is assembly.
+    fn emit_asm(&self, ast: Ast, options: &Options, f: &mut dyn Write) -> Result<(), Error> {
+        self.emit(ast, options, f)
+    }
+
+    /// Turns the artifact written by `emit` into an object file.
+    fn assemble(&self, source: &Path, object: &Path) -> Result<(), Error>;
+
+    fn link(&self, object: &Path, executable: &Path) -> Result<(), Error>;
+
+    /// Whether the backend runs programs itself with `execute` rather than
+    /// building executables.
+    fn interprets(&self) -> bool {
+        false
+    }
+
+    /// Runs `ast` in process, reading `,` from `input` and writing `.` to
+    /// `output`.
+    fn execute(
+        &self,
+        _ast: Ast,
+        _options: &Options,
+        _input: &mut dyn Read,
+        _output: &mut dyn Write,
+    ) -> Result<(), Error> {
+        Err(Error::Usage(format!(
+            "the {} backend cannot run programs without building them",
+            self.name()
+        )))
+    }
+}
+
+/// Runs an external tool of the toolchain, failing with its stderr when it
+/// does not exit successfully.
+pub fn run_tool(command: &mut Command) -> Result<(), Error> {
+    let tool = command.get_program().to_string_lossy().into_owned();
+
+    let output = command
+        .output()
+        .map_err(|e| Error::toolchain(&tool, e.to_string()))?;
+
+    check_tool(&tool, &output)?;
+
+    // warnings are still worth showing when the tool succeeds
+    if !output.stderr.is_empty() {
+        eprint!("{}", String::from_utf8_lossy(&output.stderr));
+    }
+
+    Ok(())
+}
+
+/// Turns the output of a tool which failed into an error.
+pub fn check_tool(tool: &str, output: &Output) -> Result<(), Error> {
+    if output.status.success() {
+        return Ok(());
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let message = format!("{}\n{}", output.status, stderr.trim_end());
+
+    Err(Error::toolchain(tool, message.trim_end()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        os::unix::process::ExitStatusExt,
+        process::{ExitStatus, Output},
+    };
+
+    use super::check_tool;
+    use crate::Error;
+
+    fn output(status: i32, stderr: &str) -> Output {
+        Output {
+            status: ExitStatus::from_raw(status),
+            stdout: vec![],
+            stderr: stderr.into(),
+        }
+    }
+
+    #[test]
+    fn failing_tools_report_their_stderr() {
+        assert!(check_tool("nasm", &output(0, "warning: ignored\n")).is_ok());
+
+        for tool in ["nasm", "ld", "llc", "gcc"] {
+            let error = check_tool(tool, &output(1 << 8, "code.s:3: error: parser\n"));
+
+            match error {
+                Err(Error::Toolchain {
+                    tool: name,
+                    message,
+                }) => {
+                    assert_eq!(name, tool);
+                    assert_eq!(message, "exit status: 1\ncode.s:3: error: parser");
+                }
+                other => panic!("{tool} gave {other:?}"),
+            }
+        }
+
+        let error = check_tool("ld", &output(9, "")).unwrap_err();
+        assert_eq!(error.to_string(), "ld: signal: 9 (SIGKILL)");
+    }
+}
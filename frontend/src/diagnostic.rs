@@ -0,0 +1,154 @@
+use std::fmt::Display;
+
+use crate::span::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Level {
+    Error,
+    Warning,
+}
+
+impl Display for Level {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Error => write!(f, "error"),
+            Self::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    pub primary: bool,
+}
+
+/// Error pointing into the source, rendered like rustc does with an excerpt
+/// of every line carrying a label and a caret underline below it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub message: String,
+    pub labels: Vec<Label>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            level: Level::Error,
+            message: message.into(),
+            labels: vec![],
+        }
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self {
+            level: Level::Warning,
+            ..Self::error(message)
+        }
+    }
+
+    pub fn with_primary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary: true,
+        });
+        self
+    }
+
+    pub fn with_secondary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary: false,
+        });
+        self
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.labels
+            .iter()
+            .find(|label| label.primary)
+            .or(self.labels.first())
+            .map(|label| label.span)
+    }
+
+    pub fn render(&self, name: &str, source: &str) -> String {
+        let lines = source.split('\n').collect::<Vec<_>>();
+
+        let mut labels = self.labels.iter().collect::<Vec<_>>();
+        labels.sort_by_key(|label| (label.span.line, label.span.column));
+
+        let width = labels
+            .iter()
+            .map(|label| label.span.line.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let gutter = " ".repeat(width);
+
+        let mut out = format!("{}: {}\n", self.level, self.message);
+
+        if let Some(span) = self.span() {
+            out += &format!("{gutter}--> {name}:{}:{}\n", span.line, span.column);
+        }
+
+        out += &format!("{gutter} |\n");
+
+        let mut previous: Option<usize> = None;
+
+        for label in labels {
+            let number = label.span.line;
+            let line = lines
+                .get(number - 1)
+                .map(|line| line.trim_end_matches('\r'))
+                .unwrap_or_default();
+
+            if previous != Some(number) {
+                if previous.is_some_and(|previous| number > previous + 1) {
+                    out += "...\n";
+                }
+                out += &format!("{number:>width$} | {}\n", expand_tabs(line));
+                previous = Some(number);
+            }
+
+            let before = line.chars().take(label.span.column - 1).collect::<String>();
+            let underlined = source
+                .get(label.span.offset..label.span.end())
+                .unwrap_or_default();
+            let length = underlined
+                .lines()
+                .next()
+                .map_or(0, |text| text.chars().count())
+                .max(1);
+            let marker = if label.primary { "^" } else { "-" };
+
+            out += &format!(
+                "{gutter} | {}{} {}\n",
+                " ".repeat(expand_tabs(&before).len()),
+                marker.repeat(length),
+                label.message
+            );
+        }
+
+        out += &format!("{gutter} |\n");
+
+        out
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.span() {
+            Some(span) => write!(f, "{} at {}:{}", self.message, span.line, span.column),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+fn expand_tabs(text: &str) -> String {
+    text.replace('\t', "    ")
+}
@@ -0,0 +1,64 @@
+use std::{fmt::Display, io};
+
+use crate::diagnostic::Diagnostic;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The program has bracket errors, see the diagnostics for details.
+    Parse(Vec<Diagnostic>),
+    /// Invalid command line arguments.
+    Usage(String),
+    Io {
+        context: String,
+        error: io::Error,
+    },
+    /// An external tool (nasm, ld, gcc, llc) could not be run or failed.
+    Toolchain {
+        tool: String,
+        message: String,
+    },
+    /// The compiled program could not be run or failed while running.
+    Runtime(String),
+}
+
+impl Error {
+    /// Builds an I/O error mapper, to be used as `.map_err(Error::io("code.s"))`.
+    pub fn io(context: impl Into<String>) -> impl FnOnce(io::Error) -> Error {
+        let context = context.into();
+        move |error| Error::Io { context, error }
+    }
+
+    pub fn toolchain(tool: impl Into<String>, message: impl Into<String>) -> Error {
+        Error::Toolchain {
+            tool: tool.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Parse(diagnostics) => {
+                let messages = diagnostics
+                    .iter()
+                    .map(|diagnostic| diagnostic.to_string())
+                    .collect::<Vec<_>>();
+                write!(f, "{}", messages.join("\n"))
+            }
+            Error::Usage(message) => write!(f, "{message}"),
+            Error::Io { context, error } => write!(f, "{context}: {error}"),
+            Error::Toolchain { tool, message } => write!(f, "{tool}: {message}"),
+            Error::Runtime(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
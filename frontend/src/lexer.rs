@@ -0,0 +1,58 @@
+use std::collections::VecDeque;
+
+use crate::span::{Span, Spanned};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Opcode {
+    Plus,
+    Minus,
+    Right,
+    Left,
+    Comma,
+    Dot,
+    OpenBracket,
+    CloseBracket,
+}
+
+pub type Token = Spanned<Opcode>;
+
+pub fn parse(character: char) -> Option<Opcode> {
+    match character {
+        '+' => Some(Opcode::Plus),
+        '-' => Some(Opcode::Minus),
+        '>' => Some(Opcode::Right),
+        '<' => Some(Opcode::Left),
+        ',' => Some(Opcode::Comma),
+        '.' => Some(Opcode::Dot),
+        '[' => Some(Opcode::OpenBracket),
+        ']' => Some(Opcode::CloseBracket),
+        _ => None,
+    }
+}
+
+pub fn tokenize(input: &str) -> VecDeque<Token> {
+    let mut tokens = VecDeque::new();
+    let mut line = 1;
+    let mut column = 1;
+
+    for (offset, character) in input.char_indices() {
+        if let Some(opcode) = parse(character) {
+            let span = Span {
+                offset,
+                len: character.len_utf8(),
+                line,
+                column,
+            };
+            tokens.push_back(Token::new(opcode, span));
+        }
+
+        if character == '\n' {
+            line += 1;
+            column = 1;
+        } else {
+            column += 1;
+        }
+    }
+
+    tokens
+}
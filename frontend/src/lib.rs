@@ -0,0 +1,27 @@
+pub mod backend;
+pub mod diagnostic;
+pub mod error;
+pub mod lexer;
+pub mod parser;
+pub mod span;
+
+pub use backend::{Backend, Bounds, CellWidth, Eof, OptLevel, Options};
+pub use diagnostic::{Diagnostic, Label, Level};
+pub use error::Error;
+pub use lexer::{Opcode, Token};
+pub use parser::{Ast, Expression};
+pub use span::{Span, Spanned};
+
+use lexer::tokenize;
+use parser::{generate_ast, suspicious_nesting};
+
+/// Parses `input`, returning every bracket error found in the program
+/// followed by hints about where its nesting probably went wrong.
+pub fn parse_source(input: &str) -> Result<Ast, Vec<Diagnostic>> {
+    let tokens = tokenize(input);
+
+    generate_ast(&mut tokens.clone()).map_err(|mut diagnostics| {
+        diagnostics.extend(suspicious_nesting(&tokens, input));
+        diagnostics
+    })
+}
@@ -0,0 +1,285 @@
+use std::{collections::VecDeque, fmt::Display};
+
+use crate::{
+    diagnostic::Diagnostic,
+    lexer::{Opcode, Token},
+    span::{Span, Spanned},
+};
+
+#[derive(Debug)]
+pub enum Expression {
+    Plus,
+    Minus,
+    Right,
+    Left,
+    Comma,
+    Dot,
+
+    /// Loop body and the span of its closing bracket, the expression itself
+    /// is spanned by the opening one.
+    Conditional(Ast, Span),
+}
+
+#[derive(Debug)]
+pub struct Ast(pub Vec<Spanned<Expression>>);
+
+impl Ast {
+    fn fmt_indented(&self, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
+        let indent = "  ".repeat(depth);
+
+        for expr in &self.0 {
+            let span = expr.span;
+            match &expr.node {
+                Expression::Conditional(inner, close) => {
+                    writeln!(
+                        f,
+                        "{indent}Conditional {}:{}..{}:{}",
+                        span.line, span.column, close.line, close.column
+                    )?;
+                    inner.fmt_indented(f, depth + 1)?;
+                }
+                node => writeln!(f, "{indent}{node:?} {}:{}", span.line, span.column)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for Ast {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+/// Spans of the opening and closing brackets of the last loop in `ast`.
+fn last_loop(ast: &Ast) -> Option<(Span, Span)> {
+    ast.0.iter().rev().find_map(|expr| match expr.node {
+        Expression::Conditional(_, close) => Some((expr.span, close)),
+        _ => None,
+    })
+}
+
+fn generate_ast_vec(tokens: &mut VecDeque<Token>, diagnostics: &mut Vec<Diagnostic>) -> Ast {
+    let mut ast = vec![];
+
+    while let Some(token) = tokens.pop_front() {
+        let expr = match token.node {
+            Opcode::Plus => Expression::Plus,
+            Opcode::Minus => Expression::Minus,
+            Opcode::Right => Expression::Right,
+            Opcode::Left => Expression::Left,
+            Opcode::Comma => Expression::Comma,
+            Opcode::Dot => Expression::Dot,
+            Opcode::OpenBracket => {
+                let inner = generate_ast_vec(tokens, diagnostics);
+                match tokens.pop_front() {
+                    Some(Token {
+                        node: Opcode::CloseBracket,
+                        span,
+                    }) => Expression::Conditional(inner, span),
+                    _ => {
+                        let mut diagnostic = Diagnostic::error("unclosed bracket")
+                            .with_primary(token.span, "this `[` is never closed");
+
+                        if let Some((open, close)) = last_loop(&inner) {
+                            let message = format!(
+                                "the last `]` closes the loop opened at {}:{}",
+                                open.line, open.column
+                            );
+                            diagnostic = diagnostic.with_secondary(close, message);
+                        }
+
+                        diagnostics.push(diagnostic);
+
+                        // recover by closing the loop at the end of the input
+                        Expression::Conditional(inner, token.span)
+                    }
+                }
+            }
+            Opcode::CloseBracket => {
+                tokens.push_front(token);
+                return Ast(ast);
+            }
+        };
+
+        ast.push(Spanned::new(expr, token.span));
+    }
+
+    Ast(ast)
+}
+
+/// Parses the whole token stream, recovering from unmatched brackets so that
+/// every one of them is reported at once.
+pub fn generate_ast(tokens: &mut VecDeque<Token>) -> Result<Ast, Vec<Diagnostic>> {
+    let mut diagnostics = vec![];
+    let mut block = generate_ast_vec(tokens, &mut diagnostics);
+
+    while let Some(token) = tokens.pop_front() {
+        let mut diagnostic = Diagnostic::error("unexpected closing bracket")
+            .with_primary(token.span, "unmatched `]`");
+
+        if let Some((open, close)) = last_loop(&block) {
+            let message = format!(
+                "this `[` is already closed at {}:{}",
+                close.line, close.column
+            );
+            diagnostic = diagnostic.with_secondary(open, message);
+        }
+
+        diagnostics.push(diagnostic);
+
+        // recover by dropping the bracket and parsing the rest as top level
+        let rest = generate_ast_vec(tokens, &mut diagnostics);
+        block.0.extend(rest.0);
+    }
+
+    if diagnostics.is_empty() {
+        Ok(block)
+    } else {
+        diagnostics.sort_by_key(|diagnostic| diagnostic.span().map(|span| span.offset));
+        Err(diagnostics)
+    }
+}
+
+fn indentation(line: &str) -> usize {
+    line.chars()
+        .take_while(|c| c.is_whitespace())
+        .map(|c| if c == '\t' { 4 } else { 1 })
+        .sum()
+}
+
+/// Warnings for the places where the nesting of an unbalanced program most
+/// likely went wrong, based on how its lines are indented.
+pub fn suspicious_nesting(tokens: &VecDeque<Token>, source: &str) -> Vec<Diagnostic> {
+    let lines = source.split('\n').collect::<Vec<_>>();
+    let indent = |span: Span| indentation(lines[span.line - 1]);
+
+    let mut diagnostics = vec![];
+    let mut stack = vec![];
+
+    for token in tokens {
+        match token.node {
+            Opcode::OpenBracket => stack.push(token.span),
+            Opcode::CloseBracket => {
+                let Some(open) = stack.pop() else {
+                    continue;
+                };
+
+                if open.line != token.span.line && indent(open) != indent(token.span) {
+                    let message = format!(
+                        "this `]` closes the loop opened at {}:{}",
+                        open.line, open.column
+                    );
+                    diagnostics.push(
+                        Diagnostic::warning("closing bracket indented differently from its loop")
+                            .with_primary(token.span, message)
+                            .with_secondary(open, "loop opened here"),
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for open in stack {
+        let end = lines
+            .iter()
+            .enumerate()
+            .skip(open.line)
+            .find(|(_, line)| !line.trim().is_empty() && indentation(line) <= indent(open));
+
+        if let Some((index, line)) = end {
+            let column = line.chars().take_while(|c| c.is_whitespace()).count() + 1;
+            let offset = lines[..index]
+                .iter()
+                .map(|line| line.len() + 1)
+                .sum::<usize>()
+                + line.len()
+                - line.trim_start().len();
+            let span = Span {
+                offset,
+                len: 0,
+                line: index + 1,
+                column,
+            };
+
+            diagnostics.push(
+                Diagnostic::warning("loop is never closed before the end of file")
+                    .with_primary(span, "the loop probably ends before this line")
+                    .with_secondary(open, "loop opened here"),
+            );
+        }
+    }
+
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span().map(|span| span.offset));
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parse_source, Level};
+
+    #[test]
+    fn unexpected_closing_bracket() {
+        let source = "+[-]>\n+]<";
+        let diagnostics = parse_source(source).unwrap_err();
+
+        assert_eq!(
+            diagnostics[0].render("test.bf", source),
+            r#"error: unexpected closing bracket
+ --> test.bf:2:2
+  |
+1 | +[-]>
+  |  - this `[` is already closed at 1:4
+2 | +]<
+  |  ^ unmatched `]`
+  |
+"#
+        );
+    }
+
+    #[test]
+    fn unclosed_bracket() {
+        let source = "+[[-]>\n\n\n[+]<";
+        let diagnostics = parse_source(source).unwrap_err();
+
+        assert_eq!(
+            diagnostics[0].render("test.bf", source),
+            r#"error: unclosed bracket
+ --> test.bf:1:2
+  |
+1 | +[[-]>
+  |  ^ this `[` is never closed
+...
+4 | [+]<
+  |   - the last `]` closes the loop opened at 4:1
+  |
+"#
+        );
+    }
+
+    #[test]
+    fn every_error_is_reported() {
+        let source = "[\n  [\n    +\n  -\n]\n]]+\n[\n  +\n-";
+        let diagnostics = parse_source(source).unwrap_err();
+
+        let summary = diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let span = diagnostic.span().unwrap();
+                (diagnostic.level, span.line, span.column)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            summary,
+            vec![
+                (Level::Error, 6, 2),
+                (Level::Error, 7, 1),
+                (Level::Warning, 5, 1),
+                (Level::Warning, 9, 1),
+            ]
+        );
+    }
+}
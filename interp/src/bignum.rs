@@ -0,0 +1,238 @@
+use std::{cmp::Ordering, fmt::Display};
+
+/// Integer of any size, stored as a sign and a magnitude in base 2^32 with
+/// the least significant limb first and no leading zero limbs.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BigInt {
+    negative: bool,
+    magnitude: Vec<u32>,
+}
+
+impl BigInt {
+    fn from_parts(negative: bool, mut magnitude: Vec<u32>) -> BigInt {
+        while magnitude.last() == Some(&0) {
+            magnitude.pop();
+        }
+
+        BigInt {
+            // there is no negative zero
+            negative: negative && !magnitude.is_empty(),
+            magnitude,
+        }
+    }
+
+    fn from_u64(negative: bool, value: u64) -> BigInt {
+        BigInt::from_parts(negative, vec![value as u32, (value >> 32) as u32])
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.magnitude.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    /// Lowest byte of the two's complement representation.
+    pub fn low_byte(&self) -> u8 {
+        let byte = self.magnitude.first().map_or(0, |limb| *limb as u8);
+
+        if self.negative {
+            byte.wrapping_neg()
+        } else {
+            byte
+        }
+    }
+
+    pub fn add(&self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            let magnitude = add_magnitudes(&self.magnitude, &other.magnitude);
+            return BigInt::from_parts(self.negative, magnitude);
+        }
+
+        match compare_magnitudes(&self.magnitude, &other.magnitude) {
+            Ordering::Less => BigInt::from_parts(
+                other.negative,
+                sub_magnitudes(&other.magnitude, &self.magnitude),
+            ),
+            _ => BigInt::from_parts(
+                self.negative,
+                sub_magnitudes(&self.magnitude, &other.magnitude),
+            ),
+        }
+    }
+
+    pub fn mul(&self, other: &BigInt) -> BigInt {
+        let mut magnitude = vec![0; self.magnitude.len() + other.magnitude.len()];
+
+        for (i, a) in self.magnitude.iter().enumerate() {
+            let mut carry = 0;
+            for (j, b) in other.magnitude.iter().enumerate() {
+                let product = *a as u64 * *b as u64 + magnitude[i + j] as u64 + carry;
+                magnitude[i + j] = product as u32;
+                carry = product >> 32;
+            }
+            magnitude[i + other.magnitude.len()] = carry as u32;
+        }
+
+        BigInt::from_parts(self.negative != other.negative, magnitude)
+    }
+
+    /// Divides the magnitude by `divisor`, returning the magnitude of the
+    /// quotient and the remainder.
+    pub fn div_rem(&self, divisor: u64) -> (BigInt, u64) {
+        let mut quotient = vec![0; self.magnitude.len()];
+        let mut remainder = 0u128;
+
+        for (i, limb) in self.magnitude.iter().enumerate().rev() {
+            let current = remainder << 32 | *limb as u128;
+            quotient[i] = (current / divisor as u128) as u32;
+            remainder = current % divisor as u128;
+        }
+
+        (BigInt::from_parts(false, quotient), remainder as u64)
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(value: i64) -> Self {
+        BigInt::from_u64(value < 0, value.unsigned_abs())
+    }
+}
+
+impl Display for BigInt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const CHUNK: u64 = 1_000_000_000;
+
+        // groups of nine digits, least significant first
+        let mut chunks = vec![];
+        let mut rest = self.clone();
+        loop {
+            let (quotient, remainder) = rest.div_rem(CHUNK);
+            chunks.push(remainder);
+            if quotient.is_zero() {
+                break;
+            }
+            rest = quotient;
+        }
+
+        if self.negative {
+            write!(f, "-")?;
+        }
+
+        let mut chunks = chunks.iter().rev();
+        write!(f, "{}", chunks.next().expect("at least one chunk"))?;
+        for chunk in chunks {
+            write!(f, "{chunk:09}")?;
+        }
+
+        Ok(())
+    }
+}
+
+fn compare_magnitudes(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+
+    let mut out = Vec::with_capacity(long.len() + 1);
+    let mut carry = 0;
+    for (i, limb) in long.iter().enumerate() {
+        let sum = *limb as u64 + *short.get(i).unwrap_or(&0) as u64 + carry;
+        out.push(sum as u32);
+        carry = sum >> 32;
+    }
+    out.push(carry as u32);
+
+    out
+}
+
+/// `a - b`, where `a` is not smaller than `b`.
+fn sub_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut out = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+    for (i, limb) in a.iter().enumerate() {
+        let (difference, overflow_b) = limb.overflowing_sub(*b.get(i).unwrap_or(&0));
+        let (difference, overflow_borrow) = difference.overflowing_sub(borrow);
+        out.push(difference);
+        borrow = (overflow_b || overflow_borrow) as u32;
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BigInt;
+
+    fn big(value: i64) -> BigInt {
+        value.into()
+    }
+
+    /// 2^64, the first value taking three limbs.
+    fn two_to_64() -> BigInt {
+        big(1 << 32).mul(&big(1 << 32))
+    }
+
+    #[test]
+    fn carries_and_borrows_cross_limbs() {
+        assert_eq!(big(u32::MAX as i64).add(&big(1)), big(1 << 32));
+        assert_eq!(big(i64::MAX).add(&big(i64::MAX)).add(&big(2)), two_to_64());
+        assert_eq!(two_to_64().to_string(), "18446744073709551616");
+
+        // the top limb is borrowed from and dropped
+        let max = two_to_64().add(&big(-1));
+        assert_eq!(max, BigInt::from_u64(false, u64::MAX));
+        assert_eq!(max.to_string(), "18446744073709551615");
+        assert_eq!(big(1 << 32).add(&big(-1)), big(u32::MAX as i64));
+    }
+
+    #[test]
+    fn signs_change_through_zero() {
+        assert_eq!(big(5).add(&big(-7)), big(-2));
+        assert_eq!(big(-2).add(&big(7)), big(5));
+
+        let zero = big(-2).add(&big(2));
+        assert!(zero.is_zero());
+        assert!(!zero.is_negative());
+        assert_eq!(zero, BigInt::default());
+
+        assert_eq!(
+            two_to_64().mul(&big(-1)).add(&two_to_64()),
+            BigInt::default()
+        );
+        assert_eq!(big(-3).mul(&big(-4)), big(12));
+        assert!(!big(-3).mul(&big(0)).is_negative());
+        assert_eq!(big(-1).low_byte(), 255);
+        assert_eq!(big(-256).low_byte(), 0);
+    }
+
+    #[test]
+    fn division_keeps_the_remainder() {
+        let (quotient, remainder) = two_to_64().div_rem(3);
+        assert_eq!(quotient.to_string(), "6148914691236517205");
+        assert_eq!(remainder, 1);
+
+        let (quotient, remainder) = big(-7).div_rem(2);
+        assert_eq!((quotient, remainder), (big(3), 1));
+    }
+
+    #[test]
+    fn printed_in_decimal() {
+        assert_eq!(BigInt::default().to_string(), "0");
+        assert_eq!(big(-42).to_string(), "-42");
+        assert_eq!(big(1_000_000_000).to_string(), "1000000000");
+        // inner groups of nine digits keep their leading zeros
+        let value = big(1_000_000_000_000_000_000).add(&big(5));
+        assert_eq!(value.to_string(), "1000000000000000005");
+        assert_eq!(value.mul(&big(-1)).to_string(), "-1000000000000000005");
+        assert_eq!(
+            two_to_64().mul(&two_to_64()).to_string(),
+            "340282366920938463463374607431768211456"
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use std::fmt::Display;
+
+use crate::bignum::BigInt;
+
+/// Value held by a tape cell.
+pub trait Cell: Clone + Default + Display + PartialEq {
+    /// Whether arithmetic wraps around. A loop which never counts its cell
+    /// down to zero then simply runs forever, like it does when compiled,
+    /// while without wrapping it means the program expected cells to wrap.
+    const WRAPS: bool;
+
+    fn update(&mut self, delta: i64);
+
+    /// Adds `count * factor`, what a loop running `count` times does to the
+    /// cells it adds `factor` to.
+    fn update_times(&mut self, count: &Self, factor: i64);
+
+    fn is_zero(&self) -> bool;
+
+    /// Whether the cell went below zero, which only cells that do not wrap
+    /// around can.
+    fn is_negative(&self) -> bool {
+        false
+    }
+
+    /// Value stored by `,`.
+    fn from_byte(byte: u8) -> Self;
+
+    /// Byte written by `.`.
+    fn to_byte(&self) -> u8;
+
+    /// Number of iterations after which a loop adding `delta` to this non
+    /// zero cell leaves it at zero, or `None` when it never does.
+    fn countdown(&self, delta: i64) -> Option<Self>;
+}
+
+/// Cells are integers of any size, so a loop only ends if it counts its cell
+/// down to exactly zero, and counting a cell down below zero is an error.
+impl Cell for BigInt {
+    const WRAPS: bool = false;
+
+    fn update(&mut self, delta: i64) {
+        *self = self.add(&delta.into());
+    }
+
+    fn update_times(&mut self, count: &Self, factor: i64) {
+        *self = self.add(&count.mul(&factor.into()));
+    }
+
+    fn is_zero(&self) -> bool {
+        BigInt::is_zero(self)
+    }
+
+    fn is_negative(&self) -> bool {
+        BigInt::is_negative(self)
+    }
+
+    fn from_byte(byte: u8) -> Self {
+        (byte as i64).into()
+    }
+
+    fn to_byte(&self) -> u8 {
+        self.low_byte()
+    }
+
+    fn countdown(&self, delta: i64) -> Option<Self> {
+        if self.is_negative() == (delta < 0) {
+            return None;
+        }
+
+        let (count, remainder) = self.div_rem(delta.unsigned_abs());
+        (remainder == 0).then_some(count)
+    }
+}
+
+/// Cells of a fixed width, read as unsigned numbers.
+macro_rules! wrapping_cell {
+    ($($ty:ty),*) => {$(
+        impl Cell for $ty {
+            const WRAPS: bool = true;
+
+            fn update(&mut self, delta: i64) {
+                *self = self.wrapping_add(delta as $ty);
+            }
+
+            fn update_times(&mut self, count: &Self, factor: i64) {
+                *self = self.wrapping_add(count.wrapping_mul(factor as $ty));
+            }
+
+            fn is_zero(&self) -> bool {
+                *self == 0
+            }
+
+            fn from_byte(byte: u8) -> Self {
+                byte as $ty
+            }
+
+            fn to_byte(&self) -> u8 {
+                *self as u8
+            }
+
+            fn countdown(&self, delta: i64) -> Option<Self> {
+                countdown(*self as u64, delta as u64, <$ty>::BITS).map(|count| count as $ty)
+            }
+        }
+    )*};
+}
+
+wrapping_cell!(u8, u16, u32, u64);
+
+/// Smallest `n` for which `value + n * delta` is zero modulo `2^bits`.
+fn countdown(value: u64, delta: u64, bits: u32) -> Option<u64> {
+    let mask = u64::MAX >> (64 - bits);
+    let (value, delta) = (value & mask, delta & mask);
+
+    if delta == 0 {
+        return None;
+    }
+
+    // with delta = odd * 2^shift, only multiples of 2^shift are reached
+    let shift = delta.trailing_zeros();
+    if value.trailing_zeros() < shift {
+        return None;
+    }
+
+    // inverse of the odd part modulo 2^64 by Newton's method, starting from
+    // odd itself, which is its own inverse modulo 8, each step doubles the
+    // number of correct bits
+    let odd = delta >> shift;
+    let mut inverse = odd;
+    for _ in 0..5 {
+        inverse = inverse.wrapping_mul(2u64.wrapping_sub(odd.wrapping_mul(inverse)));
+    }
+
+    let target = value.wrapping_neg() & mask;
+    Some((target >> shift).wrapping_mul(inverse) & (mask >> shift))
+}
@@ -0,0 +1,670 @@
+use std::io::{BufRead, Read, Write};
+
+use frontend::{Ast, CellWidth, Error, Expression, Options, Span};
+
+use crate::{bignum::BigInt, cell::Cell, lower, position, read};
+
+const HELP: &str = "\
+step [COUNT]         run COUNT instructions [default: 1] (s)
+next                 run the loop starting here at once, or one instruction (n)
+continue             run until a breakpoint, a watchpoint or the end (c)
+break LINE[:COLUMN]  stop before the instruction at a source position (b)
+watch cell N [== V]  stop when cell N changes, or when it becomes V (w)
+watch head [== N]    stop when the head moves, or when it reaches cell N (w)
+delete [ID]          remove a breakpoint or a watchpoint, or all of them (d)
+info                 list the breakpoints and watchpoints (i)
+tape [RADIUS]        show the cells around the head [default: 8] (t)
+quit                 stop debugging (q)
+
+An empty line repeats the last command. Every `#` in the source is a
+breakpoint on the instruction following it.
+";
+
+/// Instruction of the program, loops jump to the index of their other end.
+enum Op {
+    Plus,
+    Minus,
+    Right,
+    Left,
+    Input,
+    Output,
+    Open(usize),
+    Close(usize),
+}
+
+struct Instruction {
+    op: Op,
+    span: Span,
+}
+
+fn flatten(ast: &Ast, program: &mut Vec<Instruction>) {
+    for expr in &ast.0 {
+        let op = match &expr.node {
+            Expression::Plus => Op::Plus,
+            Expression::Minus => Op::Minus,
+            Expression::Right => Op::Right,
+            Expression::Left => Op::Left,
+            Expression::Comma => Op::Input,
+            Expression::Dot => Op::Output,
+            Expression::Conditional(body, _) => {
+                let open = program.len();
+                program.push(Instruction {
+                    op: Op::Open(0),
+                    span: expr.span,
+                });
+                flatten(body, program);
+                program[open].op = Op::Open(program.len());
+                Op::Close(open)
+            }
+        };
+
+        let span = match &expr.node {
+            Expression::Conditional(_, close) => *close,
+            _ => expr.span,
+        };
+        program.push(Instruction { op, span });
+    }
+}
+
+struct Breakpoint {
+    id: usize,
+    pc: usize,
+    /// Whether it comes from a `#` in the source.
+    marker: bool,
+}
+
+enum Watch<C> {
+    Cell {
+        cell: isize,
+        value: Option<C>,
+        last: C,
+    },
+    Head {
+        cell: Option<isize>,
+        last: usize,
+    },
+}
+
+struct Watchpoint<C> {
+    id: usize,
+    watch: Watch<C>,
+}
+
+/// How far `resume` runs the program.
+enum Until {
+    Steps(usize),
+    Reach(usize),
+    End,
+}
+
+struct Debugger<'a, C> {
+    source: &'a str,
+    options: &'a Options,
+    program: Vec<Instruction>,
+    tape: Vec<C>,
+    head: usize,
+    pc: usize,
+    /// Whether the program ended or failed and cannot go on.
+    ended: bool,
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint<C>>,
+    next_id: usize,
+    /// Input of the program, which shares the console without one.
+    input: Option<&'a mut dyn Read>,
+    console: &'a mut dyn BufRead,
+    out: &'a mut dyn Write,
+    /// Whether the program output left a line unfinished.
+    partial_line: bool,
+}
+
+impl<C: Cell> Debugger<'_, C> {
+    fn session(&mut self) -> Result<(), Error> {
+        self.show_location()?;
+
+        let mut last = String::new();
+        loop {
+            self.print(format_args!("(debug) "))?;
+            self.out.flush().map_err(Error::io("stdout"))?;
+
+            let mut line = String::new();
+            if self
+                .console
+                .read_line(&mut line)
+                .map_err(Error::io("stdin"))?
+                == 0
+            {
+                return self.say(format_args!(""));
+            }
+
+            if !line.trim().is_empty() {
+                last = line.trim().to_string();
+            }
+
+            match self.command(&last) {
+                Ok(true) => {}
+                Ok(false) => return Ok(()),
+                Err(Error::Usage(message)) => self.say(format_args!("{message}"))?,
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    /// Runs `line`, returning false once the session is over.
+    fn command(&mut self, line: &str) -> Result<bool, Error> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        let args = words.collect::<Vec<_>>();
+
+        match (command, args.as_slice()) {
+            ("s" | "step", []) => self.resume(Until::Steps(1))?,
+            ("s" | "step", [count]) => {
+                let count = count
+                    .parse()
+                    .ok()
+                    .filter(|count| *count > 0)
+                    .ok_or_else(|| Error::Usage(format!("invalid count {count:?}")))?;
+                self.resume(Until::Steps(count))?;
+            }
+            ("n" | "next", []) => match self.program.get(self.pc).map(|i| &i.op) {
+                Some(Op::Open(close)) => self.resume(Until::Reach(close + 1))?,
+                _ => self.resume(Until::Steps(1))?,
+            },
+            ("c" | "continue", []) => self.resume(Until::End)?,
+            ("b" | "break", [position]) => self.add_breakpoint(position)?,
+            ("w" | "watch", args) => self.add_watchpoint(args)?,
+            ("d" | "delete", []) => {
+                self.breakpoints.clear();
+                self.watchpoints.clear();
+            }
+            ("d" | "delete", [id]) => self.delete(id)?,
+            ("i" | "info", []) => self.info()?,
+            ("t" | "tape", []) => self.show_tape(8)?,
+            ("t" | "tape", [radius]) => {
+                let radius = radius
+                    .parse()
+                    .map_err(|_| Error::Usage(format!("invalid radius {radius:?}")))?;
+                self.show_tape(radius)?;
+            }
+            ("h" | "help", []) => self.print(format_args!("{HELP}"))?,
+            ("q" | "quit", []) => return Ok(false),
+            _ => {
+                return Err(Error::Usage(format!(
+                    "unknown command {line:?}, see `help`"
+                )))
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn resume(&mut self, until: Until) -> Result<(), Error> {
+        if self.ended {
+            return self.say(format_args!("the program is not running"));
+        }
+
+        let mut steps = 0;
+        loop {
+            if self.pc == self.program.len() {
+                self.ended = true;
+                return self.say(format_args!("the program finished"));
+            }
+
+            // the instruction it stopped on does not stop it again
+            if steps > 0 {
+                let pc = self.pc;
+                if let Some(id) = self.breakpoints.iter().find(|b| b.pc == pc).map(|b| b.id) {
+                    self.say(format_args!("breakpoint {id}"))?;
+                    return self.show_location();
+                }
+            }
+
+            if let Err(error) = self.execute() {
+                self.ended = true;
+                self.say(format_args!("error: {error}"))?;
+                return self.show_location();
+            }
+            steps += 1;
+
+            let hits = self.check_watchpoints();
+            if !hits.is_empty() {
+                for hit in hits {
+                    self.say(format_args!("{hit}"))?;
+                }
+                return self.show_location();
+            }
+
+            match until {
+                Until::Steps(count) if steps == count => return self.show_location(),
+                Until::Reach(pc) if self.pc == pc => return self.show_location(),
+                _ => {}
+            }
+        }
+    }
+
+    fn execute(&mut self) -> Result<(), Error> {
+        let zero = self.tape[self.head].is_zero();
+        let span = self.program[self.pc].span;
+
+        match self.program[self.pc].op {
+            Op::Plus => self.tape[self.head].update(1),
+            Op::Minus => {
+                let minus = |cell: &mut C| cell.update(-1);
+                lower(&mut self.tape, self.head, minus, "`-`", span, self.options)?;
+            }
+            Op::Right => self.head = position(&mut self.tape, self.head, 1, self.options)?,
+            Op::Left => self.head = position(&mut self.tape, self.head, -1, self.options)?,
+            Op::Input => self.read()?,
+            Op::Output => {
+                let byte = self.tape[self.head].to_byte();
+                self.out.write_all(&[byte]).map_err(Error::io("stdout"))?;
+                self.partial_line = byte != b'\n';
+            }
+            Op::Open(close) if zero => {
+                self.pc = close + 1;
+                return Ok(());
+            }
+            Op::Close(open) if !zero => {
+                self.pc = open + 1;
+                return Ok(());
+            }
+            Op::Open(_) | Op::Close(_) => {}
+        }
+
+        self.pc += 1;
+        Ok(())
+    }
+
+    fn read(&mut self) -> Result<(), Error> {
+        // a prompt of the program is shown before waiting for input
+        self.out.flush().map_err(Error::io("stdout"))?;
+
+        let cell = &mut self.tape[self.head];
+        match self.input.as_deref_mut() {
+            Some(input) => read(input, cell, self.options.eof),
+            None => read(&mut self.console, cell, self.options.eof),
+        }
+    }
+
+    /// Value of `cell`, counted from the origin. Cells the tape did not grow
+    /// to yet are zero.
+    fn cell(&self, cell: isize) -> C {
+        let index = self.options.tape_origin as isize + cell;
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| self.tape.get(index))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn relative(&self, index: usize) -> isize {
+        index as isize - self.options.tape_origin as isize
+    }
+
+    /// Messages of the watchpoints the last instruction triggered.
+    fn check_watchpoints(&mut self) -> Vec<String> {
+        let mut hits = vec![];
+
+        for i in 0..self.watchpoints.len() {
+            let id = self.watchpoints[i].id;
+            let (current, head) = match &self.watchpoints[i].watch {
+                Watch::Cell { cell, .. } => (self.cell(*cell), self.head),
+                Watch::Head { .. } => (C::default(), self.head),
+            };
+            let head_cell = self.relative(head);
+
+            match &mut self.watchpoints[i].watch {
+                Watch::Cell { cell, value, last } if current != *last => {
+                    let old = std::mem::replace(last, current.clone());
+                    if value.as_ref().is_none_or(|value| *value == current) {
+                        hits.push(format!(
+                            "watchpoint {id}: cell {cell} changed from {old} to {current}"
+                        ));
+                    }
+                }
+                Watch::Head { cell, last } if head != *last => {
+                    *last = head;
+                    if cell.is_none_or(|cell| cell == head_cell) {
+                        hits.push(format!("watchpoint {id}: head moved to cell {head_cell}"));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        hits
+    }
+
+    fn add_breakpoint(&mut self, position: &str) -> Result<(), Error> {
+        let invalid = || {
+            Error::Usage(format!(
+                "invalid position {position:?}, expected LINE[:COLUMN]"
+            ))
+        };
+        let (line, column) = match position.split_once(':') {
+            Some((line, column)) => (line, Some(column.parse().map_err(|_| invalid())?)),
+            None => (position, None),
+        };
+        let line = line.parse::<usize>().map_err(|_| invalid())?;
+
+        let pc = self
+            .program
+            .iter()
+            .position(|i| {
+                i.span.line == line && column.is_none_or(|column| i.span.column == column)
+            })
+            .ok_or_else(|| Error::Usage(format!("no instruction at {position}")))?;
+
+        let id = self.breakpoint(pc, false);
+        let span = self.program[pc].span;
+        self.say(format_args!(
+            "breakpoint {id} at {}:{}",
+            span.line, span.column
+        ))
+    }
+
+    fn breakpoint(&mut self, pc: usize, marker: bool) -> usize {
+        self.next_id += 1;
+        self.breakpoints.push(Breakpoint {
+            id: self.next_id,
+            pc,
+            marker,
+        });
+
+        self.next_id
+    }
+
+    fn add_watchpoint(&mut self, args: &[&str]) -> Result<(), Error> {
+        let invalid =
+            || Error::Usage("expected `watch cell N [== VALUE]` or `watch head [== CELL]`".into());
+        let number = |text: &str| text.parse::<i64>().map_err(|_| invalid());
+
+        let (watch, description) = match args {
+            ["cell", cell] | ["cell", cell, "==", _] => {
+                let cell = number(cell)? as isize;
+                let value = match args {
+                    [.., value] if args.len() == 4 => {
+                        let mut cell = C::default();
+                        cell.update(number(value)?);
+                        Some(cell)
+                    }
+                    _ => None,
+                };
+                let description = match &value {
+                    Some(value) => format!("cell {cell} == {value}"),
+                    None => format!("cell {cell}"),
+                };
+                let last = self.cell(cell);
+                (Watch::Cell { cell, value, last }, description)
+            }
+            ["head"] => (
+                Watch::Head {
+                    cell: None,
+                    last: self.head,
+                },
+                "head".into(),
+            ),
+            ["head", "==", cell] => {
+                let cell = number(cell)? as isize;
+                let watch = Watch::Head {
+                    cell: Some(cell),
+                    last: self.head,
+                };
+                (watch, format!("head == {cell}"))
+            }
+            _ => return Err(invalid()),
+        };
+
+        self.next_id += 1;
+        self.watchpoints.push(Watchpoint {
+            id: self.next_id,
+            watch,
+        });
+        let id = self.next_id;
+        self.say(format_args!("watchpoint {id}: {description}"))
+    }
+
+    fn delete(&mut self, id: &str) -> Result<(), Error> {
+        let missing = || Error::Usage(format!("no breakpoint or watchpoint {id}"));
+        let id = id.parse::<usize>().map_err(|_| missing())?;
+
+        let (breakpoints, watchpoints) = (self.breakpoints.len(), self.watchpoints.len());
+        self.breakpoints.retain(|b| b.id != id);
+        self.watchpoints.retain(|w| w.id != id);
+
+        if (breakpoints, watchpoints) == (self.breakpoints.len(), self.watchpoints.len()) {
+            return Err(missing());
+        }
+
+        Ok(())
+    }
+
+    fn info(&mut self) -> Result<(), Error> {
+        let mut lines = vec![];
+
+        for breakpoint in &self.breakpoints {
+            let span = self.program[breakpoint.pc].span;
+            let marker = if breakpoint.marker { " (#)" } else { "" };
+            lines.push((
+                breakpoint.id,
+                format!("breakpoint at {}:{}{marker}", span.line, span.column),
+            ));
+        }
+
+        for watchpoint in &self.watchpoints {
+            let description = match &watchpoint.watch {
+                Watch::Cell {
+                    cell,
+                    value: Some(value),
+                    ..
+                } => format!("watchpoint on cell {cell} == {value}"),
+                Watch::Cell { cell, .. } => format!("watchpoint on cell {cell}"),
+                Watch::Head {
+                    cell: Some(cell), ..
+                } => format!("watchpoint on head == {cell}"),
+                Watch::Head { .. } => "watchpoint on head".into(),
+            };
+            lines.push((watchpoint.id, description));
+        }
+
+        if lines.is_empty() {
+            return self.say(format_args!("no breakpoints or watchpoints"));
+        }
+
+        lines.sort();
+        for (id, description) in lines {
+            self.say(format_args!("{id:>3}  {description}"))?;
+        }
+
+        Ok(())
+    }
+
+    /// Shows the instruction about to run under its source line.
+    fn show_location(&mut self) -> Result<(), Error> {
+        let Some(instruction) = self.program.get(self.pc) else {
+            return self.say(format_args!("at the end of the program"));
+        };
+
+        let span = instruction.span;
+        let line = self
+            .source
+            .split('\n')
+            .nth(span.line - 1)
+            .unwrap_or_default()
+            .trim_end_matches('\r');
+        // tabs are kept so that the caret lines up with the source
+        let before = line
+            .chars()
+            .take(span.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+
+        let number = span.line.to_string();
+        let gutter = " ".repeat(number.len());
+
+        self.say(format_args!("at {}:{}", span.line, span.column))?;
+        self.say(format_args!("{number} | {line}"))?;
+        self.say(format_args!("{gutter} | {before}^"))
+    }
+
+    /// Shows the cells `radius` cells around the head, with their positions
+    /// from the origin.
+    fn show_tape(&mut self, radius: usize) -> Result<(), Error> {
+        let low = self.head.saturating_sub(radius);
+        let high = (self.head + radius + 1).min(self.tape.len());
+
+        let columns = (low..high)
+            .map(|i| (self.relative(i).to_string(), self.tape[i].to_string()))
+            .collect::<Vec<_>>();
+        let width = columns
+            .iter()
+            .map(|(cell, value)| cell.len().max(value.len()))
+            .max()
+            .unwrap_or(1);
+
+        let mut cells = String::from(" cell");
+        let mut values = String::from("value");
+        let mut marker = String::from("     ");
+        for (i, (cell, value)) in (low..high).zip(&columns) {
+            cells += &format!(" {cell:>width$}");
+            values += &format!(" {value:>width$}");
+            marker += &format!(" {:>width$}", if i == self.head { "^" } else { "" });
+        }
+
+        self.say(format_args!("{cells}"))?;
+        self.say(format_args!("{values}"))?;
+        self.say(format_args!("{}", marker.trim_end()))
+    }
+
+    fn print(&mut self, message: std::fmt::Arguments) -> Result<(), Error> {
+        // the debugger starts its own lines after what the program wrote
+        if self.partial_line {
+            self.partial_line = false;
+            writeln!(self.out).map_err(Error::io("stdout"))?;
+        }
+
+        self.out.write_fmt(message).map_err(Error::io("stdout"))
+    }
+
+    fn say(&mut self, message: std::fmt::Arguments) -> Result<(), Error> {
+        self.print(message)?;
+        writeln!(self.out).map_err(Error::io("stdout"))
+    }
+}
+
+fn session<'a, C: Cell>(
+    ast: &Ast,
+    source: &'a str,
+    options: &'a Options,
+    input: Option<&'a mut dyn Read>,
+    console: &'a mut dyn BufRead,
+    out: &'a mut dyn Write,
+) -> Result<(), Error> {
+    let mut program = vec![];
+    flatten(ast, &mut program);
+
+    let mut debugger = Debugger {
+        source,
+        options,
+        program,
+        tape: vec![C::default(); options.tape_size],
+        head: options.tape_origin,
+        pc: 0,
+        ended: false,
+        breakpoints: vec![],
+        watchpoints: vec![],
+        next_id: 0,
+        input,
+        console,
+        out,
+        partial_line: false,
+    };
+
+    for (offset, _) in source.match_indices('#') {
+        let next = debugger.program.iter().position(|i| i.span.offset > offset);
+        if let Some(pc) = next.filter(|pc| debugger.breakpoints.iter().all(|b| b.pc != *pc)) {
+            debugger.breakpoint(pc, true);
+        }
+    }
+
+    debugger.session()?;
+    debugger.out.flush().map_err(Error::io("stdout"))
+}
+
+/// Debugs `ast`, parsed from `source`, reading commands from `console` and
+/// writing both the program output and what the debugger shows to `out`.
+/// The program reads from `input`, or from the console without one.
+pub fn run<'a>(
+    ast: &Ast,
+    source: &'a str,
+    options: &'a Options,
+    input: Option<&'a mut dyn Read>,
+    console: &'a mut dyn BufRead,
+    out: &'a mut dyn Write,
+) -> Result<(), Error> {
+    if options.unbounded {
+        return session::<BigInt>(ast, source, options, input, console, out);
+    }
+
+    match options.cell_width {
+        CellWidth::Bits8 => session::<u8>(ast, source, options, input, console, out),
+        CellWidth::Bits16 => session::<u16>(ast, source, options, input, console, out),
+        CellWidth::Bits32 => session::<u32>(ast, source, options, input, console, out),
+        CellWidth::Bits64 => session::<u64>(ast, source, options, input, console, out),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use frontend::{parse_source, Bounds, Options};
+
+    use super::run;
+
+    /// What the debugger shows for the `commands` given on the console.
+    fn session(program: &str, options: &Options, commands: &str) -> String {
+        let ast = parse_source(program).unwrap();
+        let mut out = vec![];
+        run(
+            &ast,
+            program,
+            options,
+            Some(&mut "".as_bytes()),
+            &mut commands.as_bytes(),
+            &mut out,
+        )
+        .unwrap();
+
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn stops_on_markers_breakpoints_and_watchpoints() {
+        let shown = session(
+            "++#>+++[-<+>]<.",
+            &Options::default(),
+            "c\nw cell 0 == 4\nc\nt 1\nb 1:15\nd 2\nc\n",
+        );
+
+        assert!(shown.contains("breakpoint 1\nat 1:4\n"), "{shown}");
+        assert!(
+            shown.contains("watchpoint 2: cell 0 changed from 3 to 4\nat 1:12\n"),
+            "{shown}"
+        );
+        assert!(shown.contains(" cell 0 1\nvalue 4 1\n      ^\n"), "{shown}");
+        assert!(shown.contains("breakpoint 3\nat 1:15\n"), "{shown}");
+    }
+
+    #[test]
+    fn errors_end_the_program() {
+        let options = Options {
+            bounds: Bounds::Trap,
+            ..Options::default()
+        };
+        let shown = session("+[<]", &options, "c\ns\n");
+
+        assert!(
+            shown.contains("error: tape head out of bounds at cell -1\nat 1:3\n"),
+            "{shown}"
+        );
+        assert!(shown.contains("the program is not running"), "{shown}");
+    }
+}
@@ -0,0 +1,517 @@
+pub mod bignum;
+pub mod cell;
+pub mod debug;
+
+use std::{
+    collections::BTreeMap,
+    io::{ErrorKind, Read, Write},
+    path::Path,
+};
+
+pub use bignum::BigInt;
+pub use cell::Cell;
+use frontend::{Ast, Backend, Bounds, CellWidth, Eof, Error, Expression, Options, Span};
+
+/// Effect of a loop body made only of `+`, `-`, `<` and `>` which leaves the
+/// head where it started, such a loop is applied at once instead of running
+/// iteration by iteration.
+struct Transfer {
+    /// Change of the loop cell on each iteration.
+    delta: i64,
+    /// Changes of the other cells on each iteration, by offset from the head.
+    changes: Vec<(isize, i64)>,
+    /// Leftmost and rightmost offsets reached by the head.
+    extent: (isize, isize),
+}
+
+impl Transfer {
+    fn of(body: &Ast) -> Option<Transfer> {
+        let mut changes = BTreeMap::<isize, i64>::new();
+        let mut offset = 0;
+        let mut extent = (0, 0);
+
+        for expr in &body.0 {
+            match expr.node {
+                Expression::Plus => *changes.entry(offset).or_default() += 1,
+                Expression::Minus => *changes.entry(offset).or_default() -= 1,
+                Expression::Right => {
+                    offset += 1;
+                    extent.1 = extent.1.max(offset);
+                }
+                Expression::Left => {
+                    offset -= 1;
+                    extent.0 = extent.0.min(offset);
+                }
+                _ => return None,
+            }
+        }
+
+        if offset != 0 {
+            return None;
+        }
+
+        Some(Transfer {
+            delta: changes.remove(&0).unwrap_or(0),
+            changes: changes
+                .into_iter()
+                .filter(|(_, factor)| *factor != 0)
+                .collect(),
+            extent,
+        })
+    }
+}
+
+struct Interpreter<'a, C> {
+    options: &'a Options,
+    /// Whether loops which are a `Transfer` are applied at once.
+    transfers: bool,
+    tape: Vec<C>,
+    head: usize,
+    input: &'a mut dyn Read,
+    output: &'a mut dyn Write,
+}
+
+impl<C: Cell> Interpreter<'_, C> {
+    fn execute(&mut self, ast: &Ast) -> Result<(), Error> {
+        for expr in &ast.0 {
+            match &expr.node {
+                Expression::Plus => self.tape[self.head].update(1),
+                Expression::Minus => {
+                    let minus = |cell: &mut C| cell.update(-1);
+                    lower(
+                        &mut self.tape,
+                        self.head,
+                        minus,
+                        "`-`",
+                        expr.span,
+                        self.options,
+                    )?;
+                }
+                Expression::Right => self.head = self.position(1)?,
+                Expression::Left => self.head = self.position(-1)?,
+                Expression::Comma => {
+                    // an interactive program shows its prompt before waiting
+                    // for input
+                    self.output.flush().map_err(Error::io("stdout"))?;
+                    read(self.input, &mut self.tape[self.head], self.options.eof)?;
+                }
+                Expression::Dot => {
+                    let byte = self.tape[self.head].to_byte();
+                    self.output
+                        .write_all(&[byte])
+                        .map_err(Error::io("stdout"))?;
+                    if self.options.unbuffered {
+                        self.output.flush().map_err(Error::io("stdout"))?;
+                    }
+                }
+                Expression::Conditional(body, _) => self.repeat(body, expr.span)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn repeat(&mut self, body: &Ast, span: Span) -> Result<(), Error> {
+        if self.tape[self.head].is_zero() {
+            return Ok(());
+        }
+
+        if !self.transfers {
+            return self.iterate(body);
+        }
+
+        match Transfer::of(body) {
+            // a loop which does not change its cell never ends, whatever the
+            // cells are, it is left to run like it would when compiled
+            Some(transfer) if transfer.delta != 0 => self.transfer(&transfer, body, span),
+            _ => self.iterate(body),
+        }
+    }
+
+    fn iterate(&mut self, body: &Ast) -> Result<(), Error> {
+        while !self.tape[self.head].is_zero() {
+            self.execute(body)?;
+        }
+
+        Ok(())
+    }
+
+    fn transfer(&mut self, transfer: &Transfer, body: &Ast, span: Span) -> Result<(), Error> {
+        let cell = &self.tape[self.head];
+        let Some(count) = cell.countdown(transfer.delta) else {
+            if C::WRAPS {
+                return self.iterate(body);
+            }
+
+            return Err(Error::Runtime(format!(
+                "the loop at {}:{} never ends: it adds {} to a cell holding {} on each \
+                 iteration, which only reaches zero if cells wrap around",
+                span.line, span.column, transfer.delta, cell
+            )));
+        };
+
+        let (low, high) = transfer.extent;
+        if self.position(low).is_err() || self.position(high).is_err() {
+            // the first iteration leaves the tape and reports where
+            return self.execute(body);
+        }
+
+        for (offset, factor) in &transfer.changes {
+            let index = self.position(*offset)?;
+            let add = |cell: &mut C| cell.update_times(&count, *factor);
+            lower(&mut self.tape, index, add, "loop", span, self.options)?;
+        }
+        self.tape[self.head] = C::default();
+
+        Ok(())
+    }
+
+    fn position(&mut self, offset: isize) -> Result<usize, Error> {
+        position(&mut self.tape, self.head, offset, self.options)
+    }
+}
+
+/// Index of the cell `offset` cells away from `head`, following the bounds
+/// mode of `options`. Unchecked tapes trap as well, since the interpreter has
+/// nothing else to read outside of the tape.
+pub fn position<C: Clone + Default>(
+    tape: &mut Vec<C>,
+    head: usize,
+    offset: isize,
+    options: &Options,
+) -> Result<usize, Error> {
+    let size = tape.len() as isize;
+    let target = head as isize + offset;
+
+    match options.bounds {
+        _ if (0..size).contains(&target) => Ok(target as usize),
+        Bounds::Wrap => Ok(target.rem_euclid(size) as usize),
+        Bounds::Grow if target >= size => {
+            let size = (target as usize + 1).max(tape.len() * 2);
+            tape.resize(size, C::default());
+            Ok(target as usize)
+        }
+        _ => Err(Error::Runtime(format!(
+            "tape head out of bounds at cell {}",
+            target - options.tape_origin as isize
+        ))),
+    }
+}
+
+/// Applies `change` to the cell at `index`, failing when it takes the cell
+/// from zero or more to below zero. Unbounded cells only do that where cells
+/// of a fixed width would wrap around to their largest value, so the program
+/// relies on wrapping. A cell already below zero holds the -1 stored by `,`
+/// at the end of the input, which fixed width cells hold as their largest
+/// value as well.
+fn lower<C: Cell>(
+    tape: &mut [C],
+    index: usize,
+    change: impl FnOnce(&mut C),
+    what: &str,
+    span: Span,
+    options: &Options,
+) -> Result<(), Error> {
+    let negative = tape[index].is_negative();
+    change(&mut tape[index]);
+    if negative || !tape[index].is_negative() {
+        return Ok(());
+    }
+
+    Err(Error::Runtime(format!(
+        "the {what} at {}:{} takes cell {} below zero, which only works if cells wrap \
+         around",
+        span.line,
+        span.column,
+        index as isize - options.tape_origin as isize
+    )))
+}
+
+/// Reads a byte of `input` into `cell` for `,`, or stores what `eof` asks
+/// for at the end of the input.
+fn read<C: Cell>(input: &mut dyn Read, cell: &mut C, eof: Eof) -> Result<(), Error> {
+    let mut byte = [0];
+    loop {
+        match input.read(&mut byte) {
+            Ok(0) => break,
+            Ok(_) => {
+                *cell = C::from_byte(byte[0]);
+                return Ok(());
+            }
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(Error::io("stdin")(e)),
+        }
+    }
+
+    match eof {
+        Eof::Zero => *cell = C::default(),
+        Eof::MinusOne => {
+            *cell = C::default();
+            cell.update(-1);
+        }
+        Eof::Unchanged => {}
+    }
+
+    Ok(())
+}
+
+fn execute<C: Cell>(
+    ast: &Ast,
+    options: &Options,
+    transfers: bool,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<(), Error> {
+    let mut interpreter = Interpreter {
+        options,
+        transfers,
+        tape: vec![C::default(); options.tape_size],
+        head: options.tape_origin,
+        input,
+        output,
+    };
+
+    interpreter.execute(ast)?;
+    interpreter.output.flush().map_err(Error::io("stdout"))
+}
+
+fn interpret(
+    ast: &Ast,
+    options: &Options,
+    transfers: bool,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<(), Error> {
+    if options.unbounded {
+        return execute::<BigInt>(ast, options, transfers, input, output);
+    }
+
+    match options.cell_width {
+        CellWidth::Bits8 => execute::<u8>(ast, options, transfers, input, output),
+        CellWidth::Bits16 => execute::<u16>(ast, options, transfers, input, output),
+        CellWidth::Bits32 => execute::<u32>(ast, options, transfers, input, output),
+        CellWidth::Bits64 => execute::<u64>(ast, options, transfers, input, output),
+    }
+}
+
+/// Runs `ast` with the tape, cells and end of input behaviour of `options`,
+/// reading `,` from `input` and writing `.` to `output`. Every instruction is
+/// run as written, which makes it the reference the other backends are
+/// tested against.
+pub fn run(
+    ast: &Ast,
+    options: &Options,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<(), Error> {
+    interpret(ast, options, false, input, output)
+}
+
+/// Like `run`, but loops only adding to cells and counting their own cell
+/// down are applied at once, so they take the same time whatever the cells
+/// hold.
+pub fn run_fast(
+    ast: &Ast,
+    options: &Options,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<(), Error> {
+    interpret(ast, options, true, input, output)
+}
+
+/// Interpreter walking the `Ast`, it runs programs in process and
+/// is the only backend supporting unbounded cells.
+pub struct InterpBackend;
+
+impl InterpBackend {
+    fn no_code(&self) -> Error {
+        Error::Usage("the interp backend runs programs without generating code".into())
+    }
+}
+
+impl Backend for InterpBackend {
+    fn name(&self) -> &'static str {
+        "interp"
+    }
+
+    fn extension(&self) -> &'static str {
+        ""
+    }
+
+    fn emit(&self, _: Ast, _: &Options, _: &mut dyn Write) -> Result<(), Error> {
+        Err(self.no_code())
+    }
+
+    fn assemble(&self, _: &Path, _: &Path) -> Result<(), Error> {
+        Err(self.no_code())
+    }
+
+    fn link(&self, _: &Path, _: &Path) -> Result<(), Error> {
+        Err(self.no_code())
+    }
+
+    fn interprets(&self) -> bool {
+        true
+    }
+
+    fn execute(
+        &self,
+        ast: Ast,
+        options: &Options,
+        input: &mut dyn Read,
+        output: &mut dyn Write,
+    ) -> Result<(), Error> {
+        run_fast(&ast, options, input, output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use frontend::{parse_source, CellWidth, Eof, Error, Options};
+
+    use crate::{run, run_fast};
+
+    fn output_with(program: &str, input: &str, options: &Options) -> Result<Vec<u8>, Error> {
+        let ast = parse_source(program).unwrap();
+        let mut out = vec![];
+        run_fast(&ast, options, &mut input.as_bytes(), &mut out)?;
+
+        Ok(out)
+    }
+
+    fn output(program: &str, input: &str) -> Result<Vec<u8>, Error> {
+        let options = Options {
+            unbounded: true,
+            ..Options::default()
+        };
+
+        output_with(program, input, &options)
+    }
+
+    /// 3^50, which does not fit in 64 bits, followed by `suffix`.
+    fn power_of_three(suffix: &str) -> String {
+        format!("+{}{suffix}", "[>+++<-]>[<+>-]<".repeat(50))
+    }
+
+    #[test]
+    fn loops_count_big_values_down() {
+        let program = power_of_three("[---]+.");
+
+        assert_eq!(output(&program, "").unwrap(), [1]);
+    }
+
+    #[test]
+    fn wrapping_loops_are_rejected() {
+        assert_eq!(
+            output("+[--]", "").unwrap_err().to_string(),
+            "the loop at 1:2 never ends: it adds -2 to a cell holding 1 on each \
+             iteration, which only reaches zero if cells wrap around"
+        );
+
+        let program = power_of_three("+[---]");
+        assert_eq!(
+            output(&program, "").unwrap_err().to_string(),
+            "the loop at 1:803 never ends: it adds -3 to a cell holding \
+             717897987691852588770250 on each iteration, which only reaches zero if \
+             cells wrap around"
+        );
+    }
+
+    #[test]
+    fn cells_going_below_zero_are_rejected() {
+        let error = |program, input, eof| {
+            let options = Options {
+                unbounded: true,
+                eof,
+                tape_origin: 1,
+                ..Options::default()
+            };
+            output_with(program, input, &options)
+                .unwrap_err()
+                .to_string()
+        };
+
+        // the loop would only end after wrapping around, it is not a transfer
+        assert_eq!(
+            error("-[.-]", "", Eof::Zero),
+            "the `-` at 1:1 takes cell 0 below zero, which only works if cells wrap around"
+        );
+        assert_eq!(
+            error(">+[.--]", "", Eof::Zero),
+            "the `-` at 1:6 takes cell 1 below zero, which only works if cells wrap around"
+        );
+        assert_eq!(
+            error("+[-<->]", "", Eof::Zero),
+            "the loop at 1:2 takes cell -1 below zero, which only works if cells wrap \
+             around"
+        );
+        assert_eq!(
+            error(",+-", "", Eof::MinusOne),
+            "the `-` at 1:3 takes cell 0 below zero, which only works if cells wrap around"
+        );
+    }
+
+    #[test]
+    fn minus_one_is_stored_at_the_end_of_the_input() {
+        let options = Options {
+            unbounded: true,
+            eof: Eof::MinusOne,
+            ..Options::default()
+        };
+
+        // the usual end of input test, adding one to what was read
+        assert_eq!(output_with(",+[-.,+]", "ab", &options).unwrap(), b"ab");
+        // -1 is written as its low byte and counted up to zero
+        assert_eq!(output_with(",.,[+]+.", "", &options).unwrap(), [255, 1]);
+    }
+
+    #[test]
+    fn cells_wrap_at_their_width() {
+        // 256 is zero in 8 bit cells, the second cell tells whether the loop
+        // ran, then -1 is counted down and read at the end of the input
+        let program = format!("{}>+<[>-<[-]]>.-[-].,.", "+".repeat(256));
+
+        for (width, ran) in [(CellWidth::Bits8, 1), (CellWidth::Bits16, 0)] {
+            let options = Options {
+                cell_width: width,
+                eof: Eof::MinusOne,
+                ..Options::default()
+            };
+
+            let out = output_with(&program, "", &options).unwrap();
+            assert_eq!(out, [ran, 0, 255]);
+        }
+    }
+
+    #[test]
+    fn loops_are_only_applied_at_once_by_run_fast() {
+        let options = Options {
+            unbounded: true,
+            ..Options::default()
+        };
+        let ast = parse_source("+++[--]").unwrap();
+
+        // the reference runs the loop until its cell goes below zero
+        let error = run(&ast, &options, &mut "".as_bytes(), &mut vec![]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "the `-` at 1:6 takes cell 0 below zero, which only works if cells wrap around"
+        );
+        let error = run_fast(&ast, &options, &mut "".as_bytes(), &mut vec![]).unwrap_err();
+        assert!(error.to_string().starts_with("the loop at 1:4 never ends"));
+
+        // both agree where the loops end, 43 only reaches zero by wrapping
+        let program = "++++++[>+++++++<-]>+[>++<---]>.<<,[>+<-]>.";
+        for cell_width in [CellWidth::Bits8, CellWidth::Bits16] {
+            let options = Options {
+                cell_width,
+                ..Options::default()
+            };
+            let ast = parse_source(program).unwrap();
+            let (mut reference, mut fast) = (vec![], vec![]);
+            run(&ast, &options, &mut "a".as_bytes(), &mut reference).unwrap();
+            run_fast(&ast, &options, &mut "a".as_bytes(), &mut fast).unwrap();
+            assert_eq!(reference, fast, "{cell_width:?}");
+        }
+    }
+}
@@ -1,154 +1,72 @@
 use std::io::Write;
 
+use base::runtime::{
+    current_cell, generate_end, generate_header, generate_input, generate_output, register,
+};
+use frontend::{Bounds, Options};
+
 use crate::optimizer::{IRExpr, IR};
 
-fn generate_header(f: &mut impl Write) -> std::io::Result<()> {
-    write!(
-        f,
-        r#"global _start
-
-section .data
-  memory: times 1000 db 0
-  buffer: db 0
-
-  hexbuff: times 90 db " "
-  idxbuff: db 0xA, " idx: --  "
-  hex: db "0123456789abcdef"
-
-section .text
-"#
-    )?;
-
-    if cfg!(debug_assertions) {
-        write!(
-            f,
-            r#"
-dump:
-  mov rax, r14             ; print index
-
-  mov rcx, 0xf0
-  and rcx, rax
-  sar rcx, 4
-  mov rcx, [hex + rcx]
-  mov [idxbuff + 7], cl 
-
-  mov rcx, 0x0f
-  and rcx, rax
-  mov rcx, [hex + rcx]
-  mov [idxbuff + 8], cl 
-
-  mov rax, 1
-  mov rdi, 1
-  mov rdx, 11
-  mov rsi, idxbuff 
-  syscall
-
-  mov r13, 0
-dumploop:
-  mov rax, [memory + r13]
-
-  mov rcx, 0xf0
-  and rcx, rax
-  sar rcx, 4
-  mov rcx, [hex + rcx]
-  mov [hexbuff + r13 * 3 + 0], cl 
-
-  mov rcx, 0x0f
-  and rcx, rax
-  mov rcx, [hex + rcx]
-  mov [hexbuff + r13 * 3 + 1], cl 
-
-  inc r13 
-  cmp r13, 30
-  jnz dumploop
-
-  mov byte [hexbuff + 89], 0xA
-
-  mov rax, 1
-  mov rdi, 1
-  mov rdx, 91
-  mov rsi, hexbuff 
-  syscall
-
-  ret
-"#
-        )?;
+/// Moves the head by `v` cells, keeping it on the tape as `bounds` says.
+fn generate_step(
+    v: i32,
+    bounds: Bounds,
+    options: &Options,
+    f: &mut impl Write,
+) -> std::io::Result<()> {
+    let size = options.tape_size;
+    let (cell, register) = (
+        current_cell(options.cell_width),
+        register(options.cell_width),
+    );
+
+    writeln!(f, "  mov {cell}, {register}")?;
+
+    if bounds == Bounds::Wrap {
+        // the head is on the tape and moves forward less than its size, one
+        // subtraction brings it back
+        writeln!(f, "  add r14, {}", (v as i64).rem_euclid(size as i64))?;
+        writeln!(f, "  lea rax, [r14 - {size}]")?;
+        writeln!(f, "  cmp r14, {size}")?;
+        writeln!(f, "  cmovae r14, rax")?;
+    } else if v >= 0 {
+        writeln!(f, "  add r14, {}", v)?;
+    } else {
+        writeln!(f, "  sub r14, {}", -v)?;
     }
 
-    write!(
-        f,
-        r#"
-_start:
-  mov r15b, 0              ; current cell
-  mov r14, 0               ; index
-"#
-    )?;
-
-    Ok(())
-}
-
-fn generate_end(f: &mut impl Write) -> std::io::Result<()> {
-    writeln!(f, "  mov byte [memory + r14], r15b")?;
-
-    if cfg!(debug_assertions) {
-        writeln!(f, "  call dump")?;
+    if bounds == Bounds::Trap {
+        // a negative index is above the size as unsigned
+        writeln!(f, "  cmp r14, {size}")?;
+        writeln!(f, "  jae bounds_error")?;
     }
 
-    writeln!(
-        f,
-        r#"
-  mov rax, 60
-  xor rdi, rdi
-  syscall"#
-    )?;
+    writeln!(f, "  mov {register}, {cell}")?;
 
     Ok(())
 }
 
-fn generate_code_ast(ir: IR, f: &mut impl Write) -> std::io::Result<()> {
-    for statement in ir {
-        match statement {
-            IRExpr::Set(v) => writeln!(f, "  mov r15b, {v}"),
-            IRExpr::Update(v) => {
-                if v >= 0 {
-                    writeln!(f, "  add r15b, {}", v)
-                } else {
-                    writeln!(f, "  sub r15b, {}", -v)
-                }
-            }
-            IRExpr::Step(v) => {
-                writeln!(f, "  mov byte [memory + r14], r15b")?;
-
-                if v >= 0 {
-                    writeln!(f, "  add r14, {}", v)?;
-                } else {
-                    writeln!(f, "  sub r14, {}", -v)?;
-                }
+fn generate_code_ast(ir: IR, options: &Options, f: &mut impl Write) -> std::io::Result<()> {
+    let register = register(options.cell_width);
 
-                writeln!(f, "  mov byte r15b, [memory + r14]")?;
-                Ok(())
-            }
-            IRExpr::Input => {
-                writeln!(f, "  xor rax, rax")?;
-                writeln!(f, "  xor rdi, rdi")?;
-                writeln!(f, "  mov rdx, 1")?;
-                writeln!(f, "  mov rsi, buffer")?;
-                writeln!(f, "  syscall")?;
-                writeln!(f, "  mov byte r15b, [buffer]")?;
-                Ok(())
-            }
-            IRExpr::Output => {
-                writeln!(f, "  mov byte [buffer], r15b")?;
-                writeln!(f, "  mov rax, 1")?;
-                writeln!(f, "  mov rdi, 1")?;
-                writeln!(f, "  mov rdx, 1")?;
-                writeln!(f, "  mov rsi, buffer")?;
-                writeln!(f, "  syscall")?;
-                Ok(())
+    for statement in ir {
+        match statement.node {
+            IRExpr::Set(v) => writeln!(f, "  mov {register}, {v}"),
+            // instructions only take 32 bit immediates besides mov
+            IRExpr::Update(v) if i32::try_from(v).is_err() => {
+                writeln!(f, "  mov rax, {v}")?;
+                writeln!(f, "  add {register}, rax")
             }
+            // negative values are sign extended, -2^31 has no positive
+            // counterpart to subtract
+            IRExpr::Update(v) => writeln!(f, "  add {register}, {v}"),
+            IRExpr::Step(v) => generate_step(v, Bounds::Unchecked, options, f),
+            IRExpr::CheckedStep(v) => generate_step(v, options.bounds, options, f),
+            IRExpr::Input => generate_input(options, f),
+            IRExpr::Output => generate_output(options, f),
             IRExpr::ConditionalStart(id) => {
                 writeln!(f, "call_{id}:")?;
-                writeln!(f, "  cmp r15b, 0")?;
+                writeln!(f, "  cmp {register}, 0")?;
                 writeln!(f, "  jz end_{id}")?;
                 Ok(())
             }
@@ -163,10 +81,37 @@ fn generate_code_ast(ir: IR, f: &mut impl Write) -> std::io::Result<()> {
     Ok(())
 }
 
-pub fn generate_code(ir: IR, f: &mut impl Write) -> std::io::Result<()> {
-    generate_header(f)?;
-    generate_code_ast(ir, f)?;
-    generate_end(f)?;
+pub fn generate_code(ir: IR, options: &Options, f: &mut impl Write) -> std::io::Result<()> {
+    generate_header(options, f)?;
+    generate_code_ast(ir, options, f)?;
+    generate_end(options, f)?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use frontend::{CellWidth, Options, Span, Spanned};
+
+    use super::generate_code_ast;
+    use crate::optimizer::IRExpr;
+
+    #[test]
+    fn updates_are_sign_extended_immediates() {
+        let options = Options {
+            cell_width: CellWidth::Bits64,
+            ..Options::default()
+        };
+        let update = |v| {
+            let mut out = vec![];
+            let ir = vec![Spanned::new(IRExpr::Update(v), Span::default())];
+            generate_code_ast(ir, &options, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(update(-1), "  add r15, -1\n");
+        // -2^31 fits in an immediate, 2^31 does not
+        assert_eq!(update(i32::MIN as i64), "  add r15, -2147483648\n");
+        assert_eq!(update(1 << 31), "  mov rax, 2147483648\n  add r15, rax\n");
+    }
+}
@@ -1,37 +1,197 @@
 mod codegen;
-mod lexer;
-mod optimizer;
-mod parser;
+pub mod optimizer;
 
-use std::{
-    collections::VecDeque,
-    fs::File,
-    io::{BufWriter, Write},
-};
+use std::{io::Write, path::Path, process::Command};
 
 use codegen::generate_code;
-use lexer::parse;
-use optimizer::{convert, optimize};
-use parser::generate_ast;
-
-pub fn compile(input: String) -> Result<(), String> {
-    let mut opcodes = input
-        .chars()
-        .filter(|c| !c.is_whitespace())
-        .filter_map(parse)
-        .collect::<VecDeque<_>>();
-
-    let ast = generate_ast(&mut opcodes)?;
+use frontend::{backend::run_tool, Ast, Backend, Error, Options};
+use optimizer::{bounds_checks, convert, optimize, IRExpr, IR};
 
+/// Converts `ast` to IR, runs the optimizations of `options` over it and
+/// adds the bounds checks it asks for.
+pub fn lower(ast: Ast, options: &Options) -> IR {
     let mut call_stack = 0;
     let ir = convert(ast, &mut call_stack);
+    let ir = optimize(ir, options);
+
+    bounds_checks(ir, options)
+}
+
+/// Writes `ir` one instruction per line, indented by loop depth and followed
+/// by the source position it comes from.
+pub fn dump(ir: &IR, f: &mut impl Write) -> std::io::Result<()> {
+    let mut depth = 0;
 
-    let ir = optimize(ir);
+    for expr in ir {
+        if let IRExpr::ConditionalEnd(_) = expr.node {
+            depth -= 1;
+        }
 
-    let file = File::create("code.s").unwrap();
-    let mut f = BufWriter::new(file);
-    generate_code(ir, &mut f).unwrap();
-    f.flush().unwrap();
+        let instruction = format!("{}{:?}", "  ".repeat(depth), expr.node);
+        writeln!(
+            f,
+            "{instruction:<24} ; {}:{}",
+            expr.span.line, expr.span.column
+        )?;
+
+        if let IRExpr::ConditionalStart(_) = expr.node {
+            depth += 1;
+        }
+    }
 
     Ok(())
 }
+
+/// Writes the NASM assembly of `ast`, after running the optimizations of
+/// `options`, into `f`.
+pub fn compile(ast: Ast, options: &Options, f: &mut impl Write) -> Result<(), Error> {
+    let ir = lower(ast, options);
+
+    generate_code(ir, options, f).map_err(Error::io("assembly output"))?;
+    f.flush().map_err(Error::io("assembly output"))?;
+
+    Ok(())
+}
+
+pub fn compile_to_string(ast: Ast, options: &Options) -> Result<String, Error> {
+    let mut out = vec![];
+    compile(ast, options, &mut out)?;
+
+    Ok(String::from_utf8(out).expect("generated assembly is ascii"))
+}
+
+pub struct IrBackend;
+
+impl Backend for IrBackend {
+    fn name(&self) -> &'static str {
+        "ir"
+    }
+
+    fn extension(&self) -> &'static str {
+        "s"
+    }
+
+    fn emit(&self, ast: Ast, options: &Options, mut f: &mut dyn Write) -> Result<(), Error> {
+        compile(ast, options, &mut f)
+    }
+
+    fn assemble(&self, source: &Path, object: &Path) -> Result<(), Error> {
+        run_tool(
+            Command::new("nasm")
+                .args(["-g", "-f", "elf64", "-o"])
+                .arg(object)
+                .arg(source),
+        )
+    }
+
+    fn link(&self, object: &Path, executable: &Path) -> Result<(), Error> {
+        run_tool(Command::new("ld").arg("-o").arg(executable).arg(object))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use frontend::{parse_source, Bounds, CellWidth, Eof, OptLevel, Options};
+
+    use crate::compile_to_string;
+
+    #[test]
+    fn bounds_modes() {
+        // the loop leaves the position of the head unknown, its steps are
+        // checked
+        let step = |bounds| {
+            let options = Options {
+                opt_level: OptLevel::O1,
+                bounds,
+                tape_size: 100,
+                ..Options::default()
+            };
+            compile_to_string(parse_source(",[>>]<").unwrap(), &options).unwrap()
+        };
+
+        let unchecked = step(Bounds::Unchecked);
+        assert!(unchecked.contains("  add r14, 2\n  mov r15b, byte [rbx + r14]\n"));
+        assert!(unchecked.contains("  sub r14, 1\n  mov r15b, byte [rbx + r14]\n"));
+
+        let trap = step(Bounds::Trap);
+        assert!(trap.contains("  add r14, 2\n  cmp r14, 100\n  jae bounds_error\n"));
+        assert!(trap.contains("  sub r14, 1\n  cmp r14, 100\n  jae bounds_error\n"));
+
+        // moving left wraps as moving right by the rest of the tape
+        let wrap = step(Bounds::Wrap);
+        assert!(wrap
+            .contains("  add r14, 2\n  lea rax, [r14 - 100]\n  cmp r14, 100\n  cmovae r14, rax\n"));
+        assert!(wrap.contains("  add r14, 99\n  lea rax, [r14 - 100]\n"));
+        assert!(!wrap.contains("bounds_error"));
+
+        let grow = step(Bounds::Grow);
+        assert!(grow.contains("  add r14, 2\n  mov r15b, byte [rbx + r14]\n"));
+        assert!(!grow.contains("bounds_error"));
+    }
+
+    #[test]
+    fn steps_left_of_the_origin() {
+        let options = Options {
+            opt_level: OptLevel::O1,
+            bounds: Bounds::Trap,
+            tape_size: 10,
+            tape_origin: 5,
+            ..Options::default()
+        };
+        let asm = |program| compile_to_string(parse_source(program).unwrap(), &options).unwrap();
+
+        // the head is known to stay on the tape down to the first cell
+        assert!(asm("<<<<<.").contains("  mov r14, 5               ; index\n"));
+        assert!(!asm("<<<<<.").contains("jae bounds_error"));
+        assert!(asm("<<<<<<.").contains("  sub r14, 6\n  cmp r14, 10\n  jae bounds_error\n"));
+    }
+
+    #[test]
+    fn constants_wrap_to_the_cell_width() {
+        let program = format!("{}.[-]-.", "+".repeat(256));
+        let asm = |cell_width| {
+            let options = Options {
+                opt_level: OptLevel::O1,
+                cell_width,
+                ..Options::default()
+            };
+            compile_to_string(parse_source(&program).unwrap(), &options).unwrap()
+        };
+
+        // 256 increments are a whole turn of a byte
+        let byte = asm(CellWidth::Bits8);
+        assert!(!byte.contains("add r15b"));
+        assert!(byte.contains("  mov r15b, -1\n"));
+
+        let word = asm(CellWidth::Bits16);
+        assert!(word.contains("  add r15w, 256\n"));
+        assert!(word.contains("  mov r15w, -1\n"));
+
+        let quad = asm(CellWidth::Bits64);
+        assert!(quad.contains("  add r15, 256\n"));
+        assert!(quad.contains("  mov qword [rbx + r14 * 8], r15\n"));
+    }
+
+    #[test]
+    fn end_of_input() {
+        let modes = [
+            (Eof::Zero, "  xor edx, edx\n"),
+            (Eof::MinusOne, "  mov rdx, -1\n"),
+            (Eof::Unchanged, "  mov rdx, r15\n"),
+        ];
+
+        for (eof, value) in modes {
+            let options = Options {
+                opt_level: OptLevel::O1,
+                eof,
+                ..Options::default()
+            };
+            let asm = compile_to_string(parse_source("+,.").unwrap(), &options).unwrap();
+
+            // the increment is kept, the read may leave the cell unchanged
+            let expected =
+                format!("  add r15b, 1\n  call input\n{value}  cmp rax, 1\n  cmovne rcx, rdx\n");
+            assert!(asm.contains(&expected), "{eof:?}");
+        }
+    }
+}
@@ -1,12 +1,14 @@
 use std::{collections::HashMap, fmt::Debug};
 
-use crate::parser::{Ast, Expression};
+use frontend::{Ast, Bounds, CellWidth, Expression, OptLevel, Options, Span, Spanned};
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq)]
 pub enum IRExpr {
-    Set(i32),
-    Update(i32),
+    Set(i64),
+    Update(i64),
     Step(i32),
+    /// A step that checks the head against the bounds of the tape.
+    CheckedStep(i32),
     Input,
     Output,
     ConditionalStart(u32),
@@ -27,6 +29,7 @@ impl Debug for IRExpr {
                 x if x >= 0 => write!(f, ">{x}"),
                 x => write!(f, "<{}", x.abs()),
             },
+            Self::CheckedStep(arg0) => write!(f, "{:?}!", Self::Step(*arg0)),
             Self::Input => write!(f, ","),
             Self::Output => write!(f, "."),
             Self::ConditionalStart(arg0) => write!(f, "[({arg0})"),
@@ -35,42 +38,47 @@ impl Debug for IRExpr {
     }
 }
 
-pub type IR = Vec<IRExpr>;
+pub type IR = Vec<Spanned<IRExpr>>;
 
 pub fn convert(ast: Ast, call_stack: &mut u32) -> IR {
     let mut arr = vec![];
 
     for expr in ast.0 {
-        let ire = match expr {
+        let span = expr.span;
+        let ire = match expr.node {
             Expression::Plus => IRExpr::Update(1),
             Expression::Minus => IRExpr::Update(-1),
-            Expression::Rigth => IRExpr::Step(1),
+            Expression::Right => IRExpr::Step(1),
             Expression::Left => IRExpr::Step(-1),
             Expression::Comma => IRExpr::Input,
             Expression::Dot => IRExpr::Output,
-            Expression::Conditional(inner) => {
+            Expression::Conditional(inner, close) => {
                 let current = *call_stack;
                 *call_stack += 1;
 
-                arr.push(IRExpr::ConditionalStart(current));
+                arr.push(Spanned::new(IRExpr::ConditionalStart(current), span));
                 let inner = convert(inner, call_stack);
                 arr.extend(inner);
-                IRExpr::ConditionalEnd(current)
+                arr.push(Spanned::new(IRExpr::ConditionalEnd(current), close));
+                continue;
             }
         };
 
-        arr.push(ire);
+        arr.push(Spanned::new(ire, span));
     }
 
     arr
 }
 
 struct Rule {
-    replace: fn(&[IRExpr]) -> Option<Vec<IRExpr>>,
+    replace: fn(&[IRExpr], CellWidth) -> Option<Vec<IRExpr>>,
     length: usize,
+    /// Only apply where the cell under the head is known to be zero before
+    /// the slice: at the start of the program, after a loop or a `Set(0)`.
+    after_zero: bool,
 }
 
-fn replace(mut ir: IR, rule: &Rule) -> (IR, bool) {
+fn replace(ir: IR, rule: &Rule, width: CellWidth) -> (IR, bool) {
     let mut i = 0;
     let mut updated = false;
 
@@ -78,69 +86,102 @@ fn replace(mut ir: IR, rule: &Rule) -> (IR, bool) {
         return (ir, false);
     }
 
-    while i < ir.len() - rule.length + 1 {
-        let slice = &ir[i..i + rule.length];
+    // rules only look at the expressions, spans are kept on the side and
+    // every replacement inherits the span of the whole matched slice
+    let (mut exprs, mut spans): (Vec<_>, Vec<_>) =
+        ir.into_iter().map(|expr| (expr.node, expr.span)).unzip();
 
-        if let Some(new) = (rule.replace)(slice) {
-            ir.drain(i..i + rule.length);
+    while i < exprs.len() - rule.length + 1 {
+        let slice = &exprs[i..i + rule.length];
+        let zero = match i.checked_sub(1).map(|j| &exprs[j]) {
+            None | Some(IRExpr::ConditionalEnd(_)) | Some(IRExpr::Set(0)) => true,
+            Some(_) => false,
+        };
+
+        if let Some(new) = (rule.replace)(slice, width).filter(|_| zero || !rule.after_zero) {
+            let span = spans
+                .drain(i..i + rule.length)
+                .reduce(Span::to)
+                .unwrap_or_default();
+
+            exprs.drain(i..i + rule.length);
             for expr in new.into_iter().rev() {
-                ir.insert(i, expr);
+                exprs.insert(i, expr);
+                spans.insert(i, span);
             }
             updated = true;
         } else {
             i += 1;
         }
 
-        if ir.len() < rule.length {
+        if exprs.len() < rule.length {
             break;
         }
     }
 
+    let ir = exprs
+        .into_iter()
+        .zip(spans)
+        .map(|(expr, span)| Spanned::new(expr, span))
+        .collect();
+
     (ir, updated)
 }
 
-fn access_analysis(ir: IR) -> (IR, bool) {
-    fn reorder(accesses: &HashMap<i32, usize>, mut ir: IR, start: i32, end: i32) -> IR {
+fn access_analysis(ir: IR, _: CellWidth) -> (IR, bool) {
+    fn reorder(accesses: &HashMap<i32, usize>, mut ir: IR, start: i32, end: i32, span: Span) -> IR {
         let mut keys = accesses.keys().collect::<Vec<_>>();
         keys.sort();
         keys.reverse();
 
+        if keys.is_empty() {
+            if end != start {
+                ir.push(Spanned::new(IRExpr::Step(end - start), span));
+            }
+            return ir;
+        }
+
         for pair in keys.windows(2) {
             if let [to, from] = pair {
                 let index = accesses[*from];
                 let expr = IRExpr::Step(*to - *from);
-                ir.insert(index, expr);
+                ir.insert(index, Spanned::new(expr, span));
             }
         }
 
-        ir.insert(0, IRExpr::Step(*keys.last().unwrap() - start));
-        ir.push(IRExpr::Step(end - *keys[0]));
+        let first = IRExpr::Step(*keys.last().unwrap() - start);
+        ir.insert(0, Spanned::new(first, span));
+        ir.push(Spanned::new(IRExpr::Step(end - *keys[0]), span));
 
         ir
     }
 
-    let mut updated = false;
+    let updated = false;
     let mut accesses = HashMap::new();
     let mut new_ir = Vec::with_capacity(ir.len());
     let mut temp = vec![];
 
+    let mut segment_span: Option<Span> = None;
+
     let mut start_step = 0;
     let mut current_step = 0;
 
     for expr in ir {
-        match expr {
+        match expr.node {
             IRExpr::Step(s) => {
                 current_step += s;
+                segment_span = Some(segment_span.map_or(expr.span, |span| span.to(expr.span)));
             }
             IRExpr::Set(_) | IRExpr::Update(_) => {
-                let mut before = 0;
-
-                for (k, v) in accesses.iter() {
-                    if *k < current_step {
-                        before = *v;
-                    }
-                }
+                // groups are laid out by increasing position, a new one goes
+                // right after the closest position on its left
+                let before = accesses
+                    .iter()
+                    .filter(|(k, _)| **k < current_step)
+                    .max_by_key(|(k, _)| **k)
+                    .map_or(0, |(_, v)| *v);
                 accesses.entry(current_step).or_insert(before);
+                segment_span = Some(segment_span.map_or(expr.span, |span| span.to(expr.span)));
                 temp.insert(accesses[&current_step], expr);
 
                 for (k, v) in accesses.iter_mut() {
@@ -149,11 +190,13 @@ fn access_analysis(ir: IR) -> (IR, bool) {
                     }
                 }
             }
-            IRExpr::Input
+            IRExpr::CheckedStep(_)
+            | IRExpr::Input
             | IRExpr::Output
             | IRExpr::ConditionalStart(_)
             | IRExpr::ConditionalEnd(_) => {
-                let out = reorder(&accesses, temp, start_step, current_step);
+                let span = segment_span.take().unwrap_or(expr.span);
+                let out = reorder(&accesses, temp, start_step, current_step, span);
                 accesses.clear();
                 temp = vec![];
                 start_step = current_step;
@@ -163,21 +206,25 @@ fn access_analysis(ir: IR) -> (IR, bool) {
         }
     }
 
-    let out = reorder(&accesses, temp, start_step, current_step);
+    let span = segment_span.unwrap_or_default();
+    let out = reorder(&accesses, temp, start_step, current_step, span);
     new_ir.extend(out);
 
     (new_ir, updated)
 }
 
-fn unreachable_branch(ir: IR) -> (IR, bool) {
+fn unreachable_branch(ir: IR, _: CellWidth) -> (IR, bool) {
     let mut updated = false;
     let mut new_ir = Vec::with_capacity(ir.len());
     let mut remove = None;
 
     for expr in ir {
-        match (new_ir.last(), &expr) {
+        // loops nested in a removed one go with it
+        match (new_ir.last().map(|e: &Spanned<IRExpr>| &e.node), &expr.node) {
             (Some(IRExpr::ConditionalEnd(_)), IRExpr::ConditionalStart(id))
-            | (Some(IRExpr::Set(0)), IRExpr::ConditionalStart(id)) => {
+            | (Some(IRExpr::Set(0)), IRExpr::ConditionalStart(id))
+                if remove.is_none() =>
+            {
                 updated = true;
                 remove = Some(*id);
             }
@@ -185,7 +232,7 @@ fn unreachable_branch(ir: IR) -> (IR, bool) {
         }
 
         if let Some(r) = remove {
-            if expr == IRExpr::ConditionalEnd(r) {
+            if expr.node == IRExpr::ConditionalEnd(r) {
                 remove = None;
             }
         } else {
@@ -196,55 +243,66 @@ fn unreachable_branch(ir: IR) -> (IR, bool) {
     (new_ir, updated)
 }
 
-fn simple_rules(mut ir: IR) -> (IR, bool) {
+/// Folds constants and recognizes common loops, constants wrap around like
+/// cells of `width` do.
+fn simple_rules(mut ir: IR, width: CellWidth) -> (IR, bool) {
     let rules = [
         Rule {
-            replace: |slice| match slice {
+            replace: |slice, _| match slice {
                 [IRExpr::Update(0)] => Some(vec![]),
                 [IRExpr::Step(0)] => Some(vec![]),
                 _ => None,
             },
             length: 1,
+            after_zero: false,
         },
         Rule {
-            replace: |slice| match slice {
-                [IRExpr::Update(a), IRExpr::Update(b)] => Some(vec![IRExpr::Update(a + b)]),
+            replace: |slice, width| match slice {
+                [IRExpr::Update(a), IRExpr::Update(b)] => {
+                    Some(vec![IRExpr::Update(width.wrap(a.wrapping_add(*b)))])
+                }
                 _ => None,
             },
             length: 2,
+            after_zero: false,
         },
         Rule {
-            replace: |slice| match slice {
+            replace: |slice, _| match slice {
                 [IRExpr::Step(a), IRExpr::Step(b)] => Some(vec![IRExpr::Step(a + b)]),
                 _ => None,
             },
             length: 2,
+            after_zero: false,
         },
         Rule {
-            replace: |slice| match slice {
-                [IRExpr::Set(a), IRExpr::Update(b)] => Some(vec![IRExpr::Set(a + b)]),
+            replace: |slice, width| match slice {
+                [IRExpr::Set(a), IRExpr::Update(b)] => {
+                    Some(vec![IRExpr::Set(width.wrap(a.wrapping_add(*b)))])
+                }
                 [IRExpr::Update(_), IRExpr::Set(a)] => Some(vec![IRExpr::Set(*a)]),
                 _ => None,
             },
             length: 2,
+            after_zero: false,
         },
         Rule {
-            replace: |slice| match slice {
+            replace: |slice, _| match slice {
                 [IRExpr::ConditionalStart(_), IRExpr::Update(-1), IRExpr::ConditionalEnd(_)] => {
                     Some(vec![IRExpr::Set(0)])
                 }
                 _ => None,
             },
             length: 3,
+            after_zero: false,
         },
         Rule {
-            replace: |slice| match slice {
+            replace: |slice, width| match slice {
                 [IRExpr::Update(a), IRExpr::ConditionalStart(_), IRExpr::Step(sl), IRExpr::Update(b), IRExpr::Step(sr), IRExpr::Update(-1), IRExpr::ConditionalEnd(_)]
                     if *sl == -*sr =>
                 {
                     Some(vec![
                         IRExpr::Step(*sl),
-                        IRExpr::Update(a * b),
+                        IRExpr::Update(width.wrap(a.wrapping_mul(*b))),
                         IRExpr::Step(*sr),
                         IRExpr::Set(0),
                     ])
@@ -252,6 +310,9 @@ fn simple_rules(mut ir: IR) -> (IR, bool) {
                 _ => None,
             },
             length: 7,
+            // the loop runs as many times as the cell holds, which is only
+            // known when it was zero before the update
+            after_zero: true,
         },
     ];
 
@@ -259,22 +320,22 @@ fn simple_rules(mut ir: IR) -> (IR, bool) {
 
     for rule in &rules {
         let changed;
-        (ir, changed) = replace(ir, rule);
+        (ir, changed) = replace(ir, rule, width);
         updated |= changed;
     }
 
     (ir, updated)
 }
 
-type Optimization = fn(IR) -> (IR, bool);
+type Optimization = fn(IR, CellWidth) -> (IR, bool);
 
-fn optimize_(mut ir: IR, functions: &[Optimization]) -> IR {
+fn optimize_(mut ir: IR, functions: &[Optimization], width: CellWidth) -> IR {
     for function in functions {
         loop {
             let mut updated = false;
             let changed;
 
-            (ir, changed) = function(ir);
+            (ir, changed) = function(ir, width);
             updated |= changed;
 
             if !updated {
@@ -286,15 +347,124 @@ fn optimize_(mut ir: IR, functions: &[Optimization]) -> IR {
     ir
 }
 
-pub fn optimize(ir: IR) -> IR {
-    optimize_(ir, &[simple_rules, unreachable_branch])
+pub fn optimize(ir: IR, options: &Options) -> IR {
+    let functions: &[Optimization] = match options.opt_level {
+        OptLevel::O0 => &[],
+        OptLevel::O1 => &[simple_rules],
+        OptLevel::O2 | OptLevel::Os => &[simple_rules, unreachable_branch],
+        // offsets a tape size apart are the same cell of a wrapping tape, the
+        // accesses of a segment cannot be reordered by offset
+        OptLevel::O3 if options.bounds == Bounds::Wrap => &[simple_rules, unreachable_branch],
+        OptLevel::O3 => &[
+            simple_rules,
+            unreachable_branch,
+            access_analysis,
+            simple_rules,
+            unreachable_branch,
+        ],
+    };
+
+    optimize_(ir, functions, options.cell_width)
+}
+
+/// Turns the steps of `ir` into checked steps according to `options.bounds`.
+///
+/// When optimizing, the head position is followed from the cell it starts on
+/// and steps that provably stay on the tape are left unchecked. The position
+/// stays known across loops whose body always moves back to where it started.
+pub fn bounds_checks(ir: IR, options: &Options) -> IR {
+    // a growing tape needs no checks, running past its end is what grows it
+    if let Bounds::Unchecked | Bounds::Grow = options.bounds {
+        return ir;
+    }
+
+    let size = options.tape_size as i64;
+
+    // net movement of the loops still open, and whether their inner loops
+    // are balanced
+    let mut open = vec![];
+    let mut balanced = HashMap::new();
+
+    for expr in &ir {
+        match expr.node {
+            IRExpr::Step(s) | IRExpr::CheckedStep(s) => {
+                if let Some((step, _)) = open.last_mut() {
+                    *step += s as i64;
+                }
+            }
+            IRExpr::ConditionalStart(_) => open.push((0, true)),
+            IRExpr::ConditionalEnd(id) => {
+                let (step, inner) = open.pop().expect("loops are balanced");
+                let is_balanced = step == 0 && inner;
+                balanced.insert(id, is_balanced);
+
+                if let Some((_, inner)) = open.last_mut() {
+                    *inner &= is_balanced;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut position = (options.opt_level != OptLevel::O0).then_some(options.tape_origin as i64);
+    let mut entries = vec![];
+
+    ir.into_iter()
+        .map(|expr| {
+            let node = match expr.node {
+                IRExpr::Step(s) | IRExpr::CheckedStep(s) => {
+                    let next = position.map(|p| p + s as i64);
+                    position = next;
+
+                    match (next, options.bounds) {
+                        (Some(p), _) if (0..size).contains(&p) => IRExpr::Step(s),
+                        (Some(p), Bounds::Wrap) => {
+                            let wrapped = p.rem_euclid(size);
+                            position = Some(wrapped);
+                            IRExpr::Step((wrapped - (p - s as i64)) as i32)
+                        }
+                        _ => IRExpr::CheckedStep(s),
+                    }
+                }
+                IRExpr::ConditionalStart(id) => {
+                    entries.push(position);
+                    if !balanced[&id] {
+                        position = None;
+                    }
+                    expr.node
+                }
+                IRExpr::ConditionalEnd(id) => {
+                    let entry = entries.pop().expect("loops are balanced");
+                    position = entry.filter(|_| balanced[&id]);
+                    expr.node
+                }
+                node => node,
+            };
+
+            Spanned::new(node, expr.span)
+        })
+        .collect()
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::optimizer::{access_analysis, optimize_, simple_rules};
+    use frontend::{parse_source, Span, Spanned};
+
+    use frontend::{Bounds, CellWidth, Eof, OptLevel, Options};
+
+    use crate::optimizer::{access_analysis, bounds_checks, convert, optimize_, simple_rules};
+
+    use super::{unreachable_branch, IRExpr, IR};
+
+    fn spanned(ir: Vec<IRExpr>) -> IR {
+        ir.into_iter()
+            .map(|expr| Spanned::new(expr, Span::default()))
+            .collect()
+    }
 
-    use super::{unreachable_branch, IRExpr};
+    fn nodes(ir: IR) -> Vec<IRExpr> {
+        ir.into_iter().map(|expr| expr.node).collect()
+    }
 
     #[test]
     fn set_zero() {
@@ -306,9 +476,9 @@ mod tests {
             IRExpr::Update(2),
         ];
 
-        let out = optimize_(ir, &[simple_rules]);
+        let out = optimize_(spanned(ir), &[simple_rules], CellWidth::default());
 
-        assert_eq!(out, vec![IRExpr::Set(2)]);
+        assert_eq!(nodes(out), vec![IRExpr::Set(2)]);
     }
 
     #[test]
@@ -323,10 +493,10 @@ mod tests {
             IRExpr::ConditionalEnd(0),
         ];
 
-        let out = optimize_(ir, &[simple_rules]);
+        let out = optimize_(spanned(ir), &[simple_rules], CellWidth::default());
 
         assert_eq!(
-            out,
+            nodes(out),
             vec![
                 IRExpr::Step(1),
                 IRExpr::Update(100),
@@ -336,6 +506,24 @@ mod tests {
         );
     }
 
+    #[test]
+    fn multiply_needs_a_known_cell() {
+        let ir = vec![
+            IRExpr::Input,
+            IRExpr::Update(1),
+            IRExpr::ConditionalStart(0),
+            IRExpr::Step(-1),
+            IRExpr::Update(1),
+            IRExpr::Step(1),
+            IRExpr::Update(-1),
+            IRExpr::ConditionalEnd(0),
+        ];
+
+        let out = optimize_(spanned(ir.clone()), &[simple_rules], CellWidth::default());
+
+        assert_eq!(nodes(out), ir);
+    }
+
     #[test]
     fn rules_should_work_in_branch() {
         let ir = vec![
@@ -353,10 +541,10 @@ mod tests {
             IRExpr::ConditionalEnd(1),
         ];
 
-        let out = optimize_(ir, &[simple_rules]);
+        let out = optimize_(spanned(ir), &[simple_rules], CellWidth::default());
 
         assert_eq!(
-            out,
+            nodes(out),
             vec![
                 IRExpr::Update(1),
                 IRExpr::ConditionalStart(0),
@@ -384,10 +572,10 @@ mod tests {
             IRExpr::ConditionalEnd(1),
         ];
 
-        let out = optimize_(ir, &[unreachable_branch]);
+        let out = optimize_(spanned(ir), &[unreachable_branch], CellWidth::default());
 
         assert_eq!(
-            out,
+            nodes(out),
             vec![
                 IRExpr::Update(1),
                 IRExpr::ConditionalStart(0),
@@ -408,9 +596,9 @@ mod tests {
             IRExpr::ConditionalEnd(0),
         ];
 
-        let out = optimize_(ir, &[unreachable_branch]);
+        let out = optimize_(spanned(ir), &[unreachable_branch], CellWidth::default());
 
-        assert_eq!(out, vec![IRExpr::Set(0)]);
+        assert_eq!(nodes(out), vec![IRExpr::Set(0)]);
     }
 
     #[test]
@@ -427,10 +615,10 @@ mod tests {
             IRExpr::ConditionalEnd(0),
         ];
 
-        let out = optimize_(ir, &[unreachable_branch]);
+        let out = optimize_(spanned(ir), &[unreachable_branch], CellWidth::default());
 
         assert_eq!(
-            out,
+            nodes(out),
             vec![
                 IRExpr::Set(1),
                 IRExpr::ConditionalStart(0),
@@ -441,6 +629,33 @@ mod tests {
         );
     }
 
+    #[test]
+    fn unreachable_nested() {
+        let ir = vec![
+            IRExpr::ConditionalStart(0),
+            IRExpr::ConditionalEnd(0),
+            IRExpr::ConditionalStart(1),
+            IRExpr::ConditionalStart(2),
+            IRExpr::ConditionalEnd(2),
+            IRExpr::ConditionalStart(3),
+            IRExpr::Output,
+            IRExpr::ConditionalEnd(3),
+            IRExpr::ConditionalEnd(1),
+            IRExpr::Output,
+        ];
+
+        let out = optimize_(spanned(ir), &[unreachable_branch], CellWidth::default());
+
+        assert_eq!(
+            nodes(out),
+            vec![
+                IRExpr::ConditionalStart(0),
+                IRExpr::ConditionalEnd(0),
+                IRExpr::Output,
+            ]
+        );
+    }
+
     #[test]
     fn access_analysis_test() {
         let ir = vec![
@@ -455,11 +670,11 @@ mod tests {
             IRExpr::Step(2),
         ];
 
-        let (out, _) = access_analysis(ir);
-        let (out, _) = simple_rules(out);
+        let (out, _) = access_analysis(spanned(ir), CellWidth::default());
+        let (out, _) = simple_rules(out, CellWidth::default());
 
         assert_eq!(
-            out,
+            nodes(out),
             vec![
                 IRExpr::Update(4),
                 IRExpr::Step(1),
@@ -492,11 +707,11 @@ mod tests {
             IRExpr::Update(10),
         ];
 
-        let (out, _) = access_analysis(ir);
-        let (out, _) = simple_rules(out);
+        let (out, _) = access_analysis(spanned(ir), CellWidth::default());
+        let (out, _) = simple_rules(out, CellWidth::default());
 
         assert_eq!(
-            out,
+            nodes(out),
             vec![
                 IRExpr::Update(4),
                 IRExpr::Step(1),
@@ -513,4 +728,133 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn rewrites_keep_spans() {
+        let ast = parse_source("+\n[-]>").unwrap();
+        let mut call_stack = 0;
+        let ir = convert(ast, &mut call_stack);
+
+        let out = optimize_(ir, &[simple_rules], CellWidth::default());
+
+        assert_eq!(
+            out,
+            vec![
+                Spanned::new(
+                    IRExpr::Set(0),
+                    Span {
+                        offset: 0,
+                        len: 5,
+                        line: 1,
+                        column: 1
+                    }
+                ),
+                Spanned::new(
+                    IRExpr::Step(1),
+                    Span {
+                        offset: 5,
+                        len: 1,
+                        line: 2,
+                        column: 4
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn access_analysis_groups_by_position() {
+        let ir = vec![
+            IRExpr::Step(2),
+            IRExpr::Update(1),
+            IRExpr::Step(-1),
+            IRExpr::Update(1),
+            IRExpr::Step(-1),
+            IRExpr::Update(1),
+            IRExpr::Step(2),
+            IRExpr::Update(1),
+            IRExpr::Output,
+        ];
+
+        let (out, _) = access_analysis(spanned(ir), CellWidth::default());
+        let out = optimize_(out, &[simple_rules], CellWidth::default());
+
+        assert_eq!(
+            nodes(out),
+            vec![
+                IRExpr::Update(1),
+                IRExpr::Step(1),
+                IRExpr::Update(1),
+                IRExpr::Step(1),
+                IRExpr::Update(2),
+                IRExpr::Output,
+            ]
+        );
+    }
+
+    #[test]
+    fn bounds_checks_follow_the_head() {
+        let ir = || {
+            spanned(vec![
+                IRExpr::Step(2),
+                IRExpr::ConditionalStart(0),
+                IRExpr::Step(-1),
+                IRExpr::Update(1),
+                IRExpr::Step(1),
+                IRExpr::ConditionalEnd(0),
+                IRExpr::Step(-3),
+                IRExpr::ConditionalStart(1),
+                IRExpr::Step(1),
+                IRExpr::ConditionalEnd(1),
+                IRExpr::Step(1),
+            ])
+        };
+        let options = Options {
+            opt_level: OptLevel::O1,
+            tape_size: 10,
+            tape_origin: 0,
+            bounds: Bounds::Trap,
+            cell_width: CellWidth::default(),
+            eof: Eof::default(),
+            unbuffered: false,
+            unbounded: false,
+        };
+
+        assert_eq!(
+            nodes(bounds_checks(ir(), &options)),
+            vec![
+                IRExpr::Step(2),
+                IRExpr::ConditionalStart(0),
+                IRExpr::Step(-1),
+                IRExpr::Update(1),
+                IRExpr::Step(1),
+                IRExpr::ConditionalEnd(0),
+                IRExpr::CheckedStep(-3),
+                IRExpr::ConditionalStart(1),
+                IRExpr::CheckedStep(1),
+                IRExpr::ConditionalEnd(1),
+                IRExpr::CheckedStep(1),
+            ]
+        );
+
+        let wrap = Options {
+            bounds: Bounds::Wrap,
+            ..options
+        };
+        let out = nodes(bounds_checks(ir(), &wrap));
+
+        assert_eq!(out[6], IRExpr::Step(7));
+        assert_eq!(out[8], IRExpr::CheckedStep(1));
+    }
+
+    #[test]
+    fn constants_wrap_to_cell_width() {
+        let ir = || spanned((0..257).map(|_| IRExpr::Update(1)).collect());
+
+        let out = optimize_(ir(), &[simple_rules], CellWidth::Bits8);
+        assert_eq!(nodes(out), vec![IRExpr::Update(1)]);
+
+        let out = optimize_(ir(), &[simple_rules], CellWidth::Bits16);
+        assert_eq!(nodes(out), vec![IRExpr::Update(257)]);
+    }
 }
@@ -0,0 +1,231 @@
+use frontend::CellWidth;
+
+/// Position in the code a jump goes to, bound once it is known.
+#[derive(Clone, Copy)]
+pub struct Label(usize);
+
+/// Conditions of the jumps, as the low nibble of their opcodes.
+#[derive(Clone, Copy)]
+pub enum Condition {
+    Below = 0x2,
+    AboveEqual = 0x3,
+    Zero = 0x4,
+    NotZero = 0x5,
+    Sign = 0x8,
+}
+
+/// x86-64 machine code, encoded by hand for the few instructions the
+/// generated programs are made of. Like `ir::codegen`, rbx holds the tape,
+/// r14 the index of the head and r15 the current cell, while r12 holds the
+/// runtime and r13 the length of the tape.
+#[derive(Default)]
+pub struct Assembler {
+    pub code: Vec<u8>,
+    labels: Vec<Option<usize>>,
+    /// Positions of the 32 bit displacements of jumps and their labels.
+    fixups: Vec<(usize, Label)>,
+}
+
+impl Assembler {
+    fn emit(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    pub fn label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(self.labels.len() - 1)
+    }
+
+    pub fn bind(&mut self, label: Label) {
+        self.labels[label.0] = Some(self.code.len());
+    }
+
+    pub fn jump(&mut self, label: Label) {
+        self.emit(&[0xe9]);
+        self.displacement(label);
+    }
+
+    pub fn jump_if(&mut self, condition: Condition, label: Label) {
+        self.emit(&[0x0f, 0x80 | condition as u8]);
+        self.displacement(label);
+    }
+
+    fn displacement(&mut self, label: Label) {
+        self.fixups.push((self.code.len(), label));
+        self.emit(&[0; 4]);
+    }
+
+    /// The code with the jumps pointing to their labels.
+    pub fn finish(mut self) -> Vec<u8> {
+        for (position, label) in self.fixups {
+            let target = self.labels[label.0].expect("jumps to bound labels");
+            let displacement = target as i64 - (position as i64 + 4);
+            self.code[position..position + 4].copy_from_slice(&(displacement as i32).to_le_bytes());
+        }
+
+        self.code
+    }
+
+    /// Saves the registers the generated code uses and takes the tape, the
+    /// runtime, the length and the head from the arguments.
+    pub fn prologue(&mut self) {
+        self.emit(&[0x55]); // push rbp
+        self.emit(&[0x48, 0x89, 0xe5]); // mov rbp, rsp
+        self.emit(&[0x53]); // push rbx
+        self.emit(&[0x41, 0x54]); // push r12
+        self.emit(&[0x41, 0x55]); // push r13
+        self.emit(&[0x41, 0x56]); // push r14
+        self.emit(&[0x41, 0x57]); // push r15
+        self.emit(&[0x48, 0x83, 0xec, 0x08]); // sub rsp, 8, calls need 16 bytes alignment
+        self.emit(&[0x48, 0x89, 0xfb]); // mov rbx, rdi
+        self.emit(&[0x49, 0x89, 0xf4]); // mov r12, rsi
+        self.emit(&[0x49, 0x89, 0xd5]); // mov r13, rdx
+        self.emit(&[0x49, 0x89, 0xce]); // mov r14, rcx
+    }
+
+    /// Returns `status`, storing the head in the runtime. It can be jumped to
+    /// from anywhere, the stack is restored from rbp.
+    pub fn epilogue(&mut self, status: u32) {
+        self.emit(&[0xb8]); // mov eax, status
+        self.emit(&status.to_le_bytes());
+        self.emit(&[0x4d, 0x89, 0x34, 0x24]); // mov [r12], r14
+        self.emit(&[0x48, 0x8d, 0x65, 0xd8]); // lea rsp, [rbp - 40]
+        self.emit(&[0x41, 0x5f]); // pop r15
+        self.emit(&[0x41, 0x5e]); // pop r14
+        self.emit(&[0x41, 0x5d]); // pop r13
+        self.emit(&[0x41, 0x5c]); // pop r12
+        self.emit(&[0x5b]); // pop rbx
+        self.emit(&[0x5d]); // pop rbp
+        self.emit(&[0xc3]); // ret
+    }
+
+    /// Loads the cell under the head into r15.
+    pub fn load(&mut self, width: CellWidth) {
+        match width {
+            CellWidth::Bits8 => self.emit(&[0x46, 0x0f, 0xb6, 0x3c, 0x33]),
+            CellWidth::Bits16 => self.emit(&[0x46, 0x0f, 0xb7, 0x3c, 0x73]),
+            CellWidth::Bits32 => self.emit(&[0x46, 0x8b, 0x3c, 0xb3]),
+            CellWidth::Bits64 => self.emit(&[0x4e, 0x8b, 0x3c, 0xf3]),
+        }
+    }
+
+    /// Stores r15 into the cell under the head.
+    pub fn store(&mut self, width: CellWidth) {
+        match width {
+            CellWidth::Bits8 => self.emit(&[0x46, 0x88, 0x3c, 0x33]),
+            CellWidth::Bits16 => self.emit(&[0x66, 0x46, 0x89, 0x3c, 0x73]),
+            CellWidth::Bits32 => self.emit(&[0x46, 0x89, 0x3c, 0xb3]),
+            CellWidth::Bits64 => self.emit(&[0x4e, 0x89, 0x3c, 0xf3]),
+        }
+    }
+
+    /// Sets the flags from the width of the cell in r15.
+    pub fn test_cell(&mut self, width: CellWidth) {
+        match width {
+            CellWidth::Bits8 => self.emit(&[0x45, 0x84, 0xff]),
+            CellWidth::Bits16 => self.emit(&[0x66, 0x45, 0x85, 0xff]),
+            CellWidth::Bits32 => self.emit(&[0x45, 0x85, 0xff]),
+            CellWidth::Bits64 => self.emit(&[0x4d, 0x85, 0xff]),
+        }
+    }
+
+    // arithmetic on the cell is done on the whole of r15, narrower cells
+    // only ever look at its low part
+
+    pub fn set_cell(&mut self, value: i64) {
+        match i32::try_from(value) {
+            Ok(value) => {
+                self.emit(&[0x49, 0xc7, 0xc7]); // mov r15, imm32
+                self.emit(&value.to_le_bytes());
+            }
+            Err(_) => {
+                self.emit(&[0x49, 0xbf]); // mov r15, imm64
+                self.emit(&value.to_le_bytes());
+            }
+        }
+    }
+
+    pub fn add_cell(&mut self, value: i64) {
+        match i32::try_from(value) {
+            Ok(value) => {
+                self.emit(&[0x49, 0x81, 0xc7]); // add r15, imm32
+                self.emit(&value.to_le_bytes());
+            }
+            Err(_) => {
+                self.emit(&[0x48, 0xb8]); // mov rax, imm64
+                self.emit(&value.to_le_bytes());
+                self.emit(&[0x49, 0x01, 0xc7]); // add r15, rax
+            }
+        }
+    }
+
+    /// Sets r15 to rax.
+    pub fn cell_from_rax(&mut self) {
+        self.emit(&[0x49, 0x89, 0xc7]);
+    }
+
+    /// Adds `value` to the head.
+    pub fn step(&mut self, value: i32) {
+        self.emit(&[0x49, 0x81, 0xc6]); // add r14, imm32
+        self.emit(&value.to_le_bytes());
+    }
+
+    /// Compares the head with the length of the tape, a negative head is
+    /// above it as unsigned.
+    pub fn compare_head(&mut self) {
+        self.emit(&[0x4d, 0x39, 0xee]); // cmp r14, r13
+    }
+
+    /// Brings back a head which went past the end of the tape by less than
+    /// its length.
+    pub fn wrap_above(&mut self) {
+        self.emit(&[0x4c, 0x89, 0xf0]); // mov rax, r14
+        self.emit(&[0x4c, 0x29, 0xe8]); // sub rax, r13
+        self.compare_head();
+        self.emit(&[0x4c, 0x0f, 0x43, 0xf0]); // cmovae r14, rax
+    }
+
+    /// Brings back a head which went before the start of the tape by less
+    /// than its length.
+    pub fn wrap_below(&mut self) {
+        self.emit(&[0x4b, 0x8d, 0x04, 0x2e]); // lea rax, [r14 + r13]
+        self.emit(&[0x4d, 0x85, 0xf6]); // test r14, r14
+        self.emit(&[0x4c, 0x0f, 0x48, 0xf0]); // cmovs r14, rax
+    }
+
+    /// Calls `function` with the runtime and `argument`, its result is left
+    /// in rax.
+    pub fn call(&mut self, function: *const (), argument: Argument) {
+        self.emit(&[0x4c, 0x89, 0xe7]); // mov rdi, r12
+        match argument {
+            Argument::None => {}
+            Argument::Head => self.emit(&[0x4c, 0x89, 0xf6]), // mov rsi, r14
+            Argument::Cell => self.emit(&[0x4c, 0x89, 0xfe]), // mov rsi, r15
+        }
+        self.emit(&[0x48, 0xb8]); // mov rax, imm64
+        self.emit(&(function as u64).to_le_bytes());
+        self.emit(&[0xff, 0xd0]); // call rax
+    }
+
+    pub fn test_rax(&mut self) {
+        self.emit(&[0x48, 0x85, 0xc0]);
+    }
+
+    pub fn compare_rax_minus_one(&mut self) {
+        self.emit(&[0x48, 0x83, 0xf8, 0xff]);
+    }
+
+    /// Takes the tape from rax and its length from the runtime, after it
+    /// grew.
+    pub fn reload_tape(&mut self) {
+        self.emit(&[0x48, 0x89, 0xc3]); // mov rbx, rax
+        self.emit(&[0x4d, 0x8b, 0x6c, 0x24, 0x08]); // mov r13, [r12 + 8]
+    }
+}
+
+/// Second argument of a call, after the runtime.
+pub enum Argument {
+    None,
+    Head,
+    Cell,
+}
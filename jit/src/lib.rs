@@ -0,0 +1,182 @@
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+mod asm;
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+mod memory;
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+mod native;
+
+use std::{
+    io::{Read, Write},
+    path::Path,
+};
+
+use frontend::{Ast, Backend, Error, Options};
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+use native::execute;
+
+/// Stands in for `native::execute` on other targets, the generated code is
+/// x86-64 and its memory is mapped with Linux system calls.
+#[cfg(not(all(target_arch = "x86_64", target_os = "linux")))]
+fn execute(_: Ast, _: &Options, _: &mut dyn Read, _: &mut dyn Write) -> Result<(), Error> {
+    Err(Error::Usage(
+        "the jit backend only runs on x86-64 Linux".into(),
+    ))
+}
+
+/// Compiles the optimized IR of `ast` to machine code in memory and runs it
+/// with the options of `options`, reading `,` from `input` and writing `.`
+/// to `output`.
+pub fn run(
+    ast: Ast,
+    options: &Options,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<(), Error> {
+    if options.unbounded {
+        return Err(Error::Usage(
+            "the jit backend does not support unbounded cells".into(),
+        ));
+    }
+
+    execute(ast, options, input, output)
+}
+
+/// Generates x86-64 machine code from the optimized IR and runs it in
+/// process, without an assembler or a linker.
+pub struct JitBackend;
+
+impl JitBackend {
+    fn no_code(&self) -> Error {
+        Error::Usage("the jit backend runs programs without generating code".into())
+    }
+}
+
+impl Backend for JitBackend {
+    fn name(&self) -> &'static str {
+        "jit"
+    }
+
+    fn extension(&self) -> &'static str {
+        ""
+    }
+
+    fn emit(&self, _: Ast, _: &Options, _: &mut dyn Write) -> Result<(), Error> {
+        Err(self.no_code())
+    }
+
+    fn assemble(&self, _: &Path, _: &Path) -> Result<(), Error> {
+        Err(self.no_code())
+    }
+
+    fn link(&self, _: &Path, _: &Path) -> Result<(), Error> {
+        Err(self.no_code())
+    }
+
+    fn interprets(&self) -> bool {
+        true
+    }
+
+    fn execute(
+        &self,
+        ast: Ast,
+        options: &Options,
+        input: &mut dyn Read,
+        output: &mut dyn Write,
+    ) -> Result<(), Error> {
+        run(ast, options, input, output)
+    }
+}
+
+#[cfg(all(test, target_arch = "x86_64", target_os = "linux"))]
+mod tests {
+    use frontend::{parse_source, Bounds, CellWidth, Eof, OptLevel, Options};
+
+    use crate::run;
+
+    /// Every tape and cell setting runs like the reference interpreter.
+    #[test]
+    fn agrees_with_the_interpreter() {
+        // the head lands on the first cell past the end, where the
+        // interpreter stops too
+        let far = format!("{}+.", ">".repeat(90));
+        let programs = [
+            (
+                "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.",
+                "",
+            ),
+            (",.,.,.,.,.", "abc"),
+            (">,>,>,>,<[.<]", "abc"),
+            ("+[->+>+<<]>>[-<+>]<<,.", ""),
+            ("<<<<<<<<<<<+.", ""),
+            (&far, ""),
+        ];
+        let settings = [
+            (Bounds::Trap, CellWidth::Bits8, Eof::Zero),
+            (Bounds::Wrap, CellWidth::Bits16, Eof::MinusOne),
+            (Bounds::Grow, CellWidth::Bits32, Eof::Unchanged),
+            (Bounds::Unchecked, CellWidth::Bits64, Eof::Zero),
+        ];
+
+        for (program, input) in programs {
+            for (bounds, cell_width, eof) in settings {
+                let options = Options {
+                    opt_level: OptLevel::O3,
+                    tape_size: 100,
+                    tape_origin: 10,
+                    bounds,
+                    cell_width,
+                    eof,
+                    ..Options::default()
+                };
+
+                let ast = parse_source(program).unwrap();
+                let mut expected = vec![];
+                let reference = interp::run(&ast, &options, &mut input.as_bytes(), &mut expected)
+                    .map_err(|e| e.to_string());
+
+                let mut out = vec![];
+                let result =
+                    run(ast, &options, &mut input.as_bytes(), &mut out).map_err(|e| e.to_string());
+
+                assert_eq!(
+                    (out, result),
+                    (expected, reference),
+                    "{program} with {bounds:?}"
+                );
+            }
+        }
+    }
+
+    /// Offsets a tape size apart are the same cell of a wrapping tape.
+    #[test]
+    fn wrapping_tapes_agree_with_the_interpreter() {
+        let programs = [(">>>[-]<<<+.", ""), (">>>>,<<<<+.<<<<<<.", "a")];
+
+        for (program, input) in programs {
+            let options = |opt_level| Options {
+                opt_level,
+                bounds: Bounds::Wrap,
+                tape_size: 3,
+                ..Options::default()
+            };
+
+            let ast = parse_source(program).unwrap();
+            let mut expected = vec![];
+            interp::run(
+                &ast,
+                &options(OptLevel::O0),
+                &mut input.as_bytes(),
+                &mut expected,
+            )
+            .unwrap();
+
+            for opt_level in [OptLevel::O0, OptLevel::O1, OptLevel::O2, OptLevel::O3] {
+                let ast = parse_source(program).unwrap();
+                let mut out = vec![];
+                run(ast, &options(opt_level), &mut input.as_bytes(), &mut out).unwrap();
+
+                assert_eq!(out, expected, "{program} at {opt_level:?}");
+            }
+        }
+    }
+}
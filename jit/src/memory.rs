@@ -0,0 +1,73 @@
+use std::{ffi::c_void, io, ptr};
+
+const PROT_READ: i32 = 1;
+const PROT_WRITE: i32 = 2;
+const PROT_EXEC: i32 = 4;
+const MAP_PRIVATE: i32 = 2;
+const MAP_ANONYMOUS: i32 = 0x20;
+
+extern "C" {
+    fn mmap(
+        addr: *mut c_void,
+        len: usize,
+        prot: i32,
+        flags: i32,
+        fd: i32,
+        offset: i64,
+    ) -> *mut c_void;
+    fn mprotect(addr: *mut c_void, len: usize, prot: i32) -> i32;
+    fn munmap(addr: *mut c_void, len: usize) -> i32;
+}
+
+/// Pages holding machine code, mapped writable to copy the code in and then
+/// only readable and executable.
+pub struct Executable {
+    address: *mut c_void,
+    len: usize,
+}
+
+impl Executable {
+    pub fn new(code: &[u8]) -> io::Result<Executable> {
+        let len = code.len().max(1);
+
+        // SAFETY: a fresh anonymous mapping, it aliases nothing
+        let address = unsafe {
+            mmap(
+                ptr::null_mut(),
+                len,
+                PROT_READ | PROT_WRITE,
+                MAP_PRIVATE | MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if address as isize == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let executable = Executable { address, len };
+
+        // SAFETY: the mapping is writable and at least as long as the code
+        unsafe {
+            ptr::copy_nonoverlapping(code.as_ptr(), address.cast(), code.len());
+            if mprotect(address, len, PROT_READ | PROT_EXEC) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        Ok(executable)
+    }
+
+    pub fn address(&self) -> *const u8 {
+        self.address.cast()
+    }
+}
+
+impl Drop for Executable {
+    fn drop(&mut self) {
+        // SAFETY: the mapping is owned and nothing runs from it anymore
+        unsafe {
+            munmap(self.address, self.len);
+        }
+    }
+}
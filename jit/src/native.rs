@@ -0,0 +1,336 @@
+//! Code generated for x86-64 Linux and the runtime it calls back into.
+
+use std::{
+    collections::HashMap,
+    io::{ErrorKind, Read, Write},
+    mem,
+};
+
+use frontend::{Ast, Bounds, CellWidth, Eof, Error, Options};
+use ir::optimizer::{IRExpr, IR};
+
+use crate::{
+    asm::{self, Argument, Assembler, Condition},
+    memory::Executable,
+};
+
+/// Size of the chunks input is read by and output is written by.
+const BUFFER: usize = 8192;
+
+/// Status returned by the generated code.
+const DONE: u32 = 0;
+const OUT_OF_BOUNDS: u32 = 1;
+const IO_ERROR: u32 = 2;
+
+/// State of a running program shared with the generated code, which reads
+/// and writes the head and the length of the tape at the start of it.
+#[repr(C)]
+struct Runtime<'a, C> {
+    head: usize,
+    len: usize,
+    tape: Vec<C>,
+    options: &'a Options,
+    input: &'a mut dyn Read,
+    output: &'a mut dyn Write,
+    read: Vec<u8>,
+    /// Position of the next byte of `read` to hand out.
+    next: usize,
+    written: Vec<u8>,
+    /// Error behind an `IO_ERROR` status.
+    error: Option<Error>,
+}
+
+impl<C> Runtime<'_, C> {
+    fn read(&mut self) -> Result<Option<u8>, Error> {
+        if self.next == self.read.len() && !self.refill()? {
+            return Ok(None);
+        }
+
+        self.next += 1;
+        Ok(Some(self.read[self.next - 1]))
+    }
+
+    /// Reads the next chunk of input, returning false at its end.
+    fn refill(&mut self) -> Result<bool, Error> {
+        // an interactive program shows its prompt before waiting for input
+        self.flush()?;
+
+        self.read.resize(BUFFER, 0);
+        loop {
+            match self.input.read(&mut self.read) {
+                Ok(n) => {
+                    self.read.truncate(n);
+                    self.next = 0;
+                    return Ok(n > 0);
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(Error::io("stdin")(e)),
+            }
+        }
+    }
+
+    fn write(&mut self, byte: u8) -> Result<(), Error> {
+        self.written.push(byte);
+
+        if self.options.unbuffered || self.written.len() == BUFFER {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.output
+            .write_all(&self.written)
+            .and_then(|_| self.output.flush())
+            .map_err(Error::io("stdout"))?;
+        self.written.clear();
+
+        Ok(())
+    }
+}
+
+// the functions called by the generated code, they report failures in their
+// result since nothing can unwind through it
+
+/// Returns the next input byte, -1 at the end of the input or -2 on error.
+extern "sysv64" fn input<C>(runtime: *mut Runtime<C>) -> i64 {
+    // SAFETY: the generated code passes the runtime it was given
+    let runtime = unsafe { &mut *runtime };
+
+    match runtime.read() {
+        Ok(Some(byte)) => byte as i64,
+        Ok(None) => -1,
+        Err(error) => {
+            runtime.error = Some(error);
+            -2
+        }
+    }
+}
+
+/// Writes the low byte of `cell`, returning 1 on error.
+extern "sysv64" fn output<C>(runtime: *mut Runtime<C>, cell: u64) -> u64 {
+    // SAFETY: the generated code passes the runtime it was given
+    let runtime = unsafe { &mut *runtime };
+
+    match runtime.write(cell as u8) {
+        Ok(()) => 0,
+        Err(error) => {
+            runtime.error = Some(error);
+            1
+        }
+    }
+}
+
+/// Grows the tape to hold the cell at `head`, returning where it now is, or
+/// null when the head went before its start.
+extern "sysv64" fn grow<C: Copy + Default>(runtime: *mut Runtime<C>, head: u64) -> *mut C {
+    // SAFETY: the generated code passes the runtime it was given
+    let runtime = unsafe { &mut *runtime };
+
+    if (head as i64) < 0 {
+        return std::ptr::null_mut();
+    }
+
+    let size = (head as usize + 1).max(runtime.tape.len() * 2);
+    runtime.tape.resize(size, C::default());
+    runtime.len = size;
+
+    runtime.tape.as_mut_ptr()
+}
+
+/// Exits of the generated code.
+struct Exits {
+    out_of_bounds: asm::Label,
+    io_error: asm::Label,
+}
+
+/// Moves the head by `value` cells, keeping it on the tape as `bounds`
+/// says.
+fn step<C: Copy + Default>(
+    f: &mut Assembler,
+    value: i32,
+    bounds: Bounds,
+    options: &Options,
+    exits: &Exits,
+) {
+    let width = options.cell_width;
+    f.store(width);
+
+    match bounds {
+        Bounds::Wrap => {
+            // the head is on the tape and moves by less than its size, one
+            // correction brings it back
+            let value = (value as i64 % options.tape_size as i64) as i32;
+            f.step(value);
+            if value >= 0 {
+                f.wrap_above();
+            } else {
+                f.wrap_below();
+            }
+        }
+        Bounds::Trap => {
+            f.step(value);
+            f.compare_head();
+            f.jump_if(Condition::AboveEqual, exits.out_of_bounds);
+        }
+        Bounds::Grow => {
+            let inside = f.label();
+            f.step(value);
+            f.compare_head();
+            f.jump_if(Condition::Below, inside);
+            f.call(grow::<C> as *const (), Argument::Head);
+            f.test_rax();
+            f.jump_if(Condition::Zero, exits.out_of_bounds);
+            f.reload_tape();
+            f.bind(inside);
+        }
+        Bounds::Unchecked => f.step(value),
+    }
+
+    f.load(width);
+}
+
+/// Compiles `ir` to a function taking the tape, the runtime, the length of
+/// the tape and the head, and returning one of the statuses.
+fn compile<C: Copy + Default>(ir: &IR, options: &Options) -> Vec<u8> {
+    let width = options.cell_width;
+    let mut f = Assembler::default();
+    let exits = Exits {
+        out_of_bounds: f.label(),
+        io_error: f.label(),
+    };
+    let mut loops = HashMap::new();
+
+    f.prologue();
+    f.load(width);
+
+    for expr in ir {
+        match expr.node {
+            IRExpr::Set(v) => f.set_cell(v),
+            IRExpr::Update(v) => f.add_cell(v),
+            // a growing tape is checked on every step, steps are only
+            // checked when they may leave the tape otherwise
+            IRExpr::Step(v) if options.bounds == Bounds::Grow => {
+                step::<C>(&mut f, v, Bounds::Grow, options, &exits)
+            }
+            IRExpr::Step(v) => step::<C>(&mut f, v, Bounds::Unchecked, options, &exits),
+            IRExpr::CheckedStep(v) => step::<C>(&mut f, v, options.bounds, options, &exits),
+            IRExpr::Input => {
+                let (end, done) = (f.label(), f.label());
+                f.call(input::<C> as *const (), Argument::None);
+                f.test_rax();
+                f.jump_if(Condition::Sign, end);
+                f.cell_from_rax();
+                f.jump(done);
+
+                f.bind(end);
+                f.compare_rax_minus_one();
+                f.jump_if(Condition::NotZero, exits.io_error);
+                match options.eof {
+                    Eof::Zero => f.set_cell(0),
+                    Eof::MinusOne => f.set_cell(-1),
+                    Eof::Unchanged => {}
+                }
+                f.bind(done);
+            }
+            IRExpr::Output => {
+                f.call(output::<C> as *const (), Argument::Cell);
+                f.test_rax();
+                f.jump_if(Condition::NotZero, exits.io_error);
+            }
+            IRExpr::ConditionalStart(id) => {
+                let (body, end) = (f.label(), f.label());
+                f.test_cell(width);
+                f.jump_if(Condition::Zero, end);
+                f.bind(body);
+                loops.insert(id, (body, end));
+            }
+            IRExpr::ConditionalEnd(id) => {
+                let (body, end) = loops[&id];
+                f.test_cell(width);
+                f.jump_if(Condition::NotZero, body);
+                f.bind(end);
+            }
+        }
+    }
+
+    f.store(width);
+    f.epilogue(DONE);
+    f.bind(exits.out_of_bounds);
+    f.epilogue(OUT_OF_BOUNDS);
+    f.bind(exits.io_error);
+    f.epilogue(IO_ERROR);
+
+    f.finish()
+}
+
+type Program<C> = unsafe extern "sysv64" fn(*mut C, *mut Runtime<C>, usize, usize) -> u32;
+
+fn execute_with<C: Copy + Default>(
+    ast: Ast,
+    options: &Options,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<(), Error> {
+    // the tape lives in this process, so leaving it is never left unchecked
+    let lowering = match options.bounds {
+        Bounds::Unchecked => Options {
+            bounds: Bounds::Trap,
+            ..options.clone()
+        },
+        _ => options.clone(),
+    };
+    let code = compile::<C>(&ir::lower(ast, &lowering), &lowering);
+    let executable = Executable::new(&code).map_err(Error::io("executable memory"))?;
+
+    let mut runtime = Runtime {
+        head: options.tape_origin,
+        len: options.tape_size,
+        tape: vec![C::default(); options.tape_size],
+        options,
+        input,
+        output,
+        read: Vec::with_capacity(BUFFER),
+        next: 0,
+        written: Vec::with_capacity(BUFFER),
+        error: None,
+    };
+
+    let tape = runtime.tape.as_mut_ptr();
+    let (len, head) = (runtime.len, runtime.head);
+    // SAFETY: the code was generated for this signature, it only touches the
+    // tape within its length and hands the runtime back to the functions
+    // above
+    let status = unsafe {
+        let program: Program<C> = mem::transmute(executable.address());
+        program(tape, &mut runtime, len, head)
+    };
+
+    // what was written before an error is still shown
+    runtime.flush()?;
+
+    match status {
+        DONE => Ok(()),
+        OUT_OF_BOUNDS => Err(Error::Runtime(format!(
+            "tape head out of bounds at cell {}",
+            runtime.head as isize - options.tape_origin as isize
+        ))),
+        _ => Err(runtime.error.take().expect("an error behind the status")),
+    }
+}
+
+/// Runs `ast` on a tape of cells as wide as `options` asks for.
+pub fn execute(
+    ast: Ast,
+    options: &Options,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<(), Error> {
+    match options.cell_width {
+        CellWidth::Bits8 => execute_with::<u8>(ast, options, input, output),
+        CellWidth::Bits16 => execute_with::<u16>(ast, options, input, output),
+        CellWidth::Bits32 => execute_with::<u32>(ast, options, input, output),
+        CellWidth::Bits64 => execute_with::<u64>(ast, options, input, output),
+    }
+}
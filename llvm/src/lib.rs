@@ -1,106 +1,144 @@
 use std::{
-    collections::VecDeque,
-    fs::File,
-    io::{BufWriter, Write},
-    process::{exit, Command},
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
 };
 
-#[derive(Debug)]
-enum Opcode {
-    Plus,
-    Minus,
-    Rigth,
-    Left,
-    Comma,
-    Dot,
-    OpenBracket,
-    CloseBracket,
-}
+use frontend::{
+    backend::{check_tool, run_tool, Options},
+    Ast, Backend, Bounds, CellWidth, Eof, Error,
+};
+use ir::optimizer::{IRExpr, IR};
 
-fn parse(character: char) -> Option<Opcode> {
-    match character {
-        '+' => Some(Opcode::Plus),
-        '-' => Some(Opcode::Minus),
-        '>' => Some(Opcode::Rigth),
-        '<' => Some(Opcode::Left),
-        ',' => Some(Opcode::Comma),
-        '.' => Some(Opcode::Dot),
-        '[' => Some(Opcode::OpenBracket),
-        ']' => Some(Opcode::CloseBracket),
-        _ => None,
-    }
-}
+fn codegen_header(
+    options: &Options,
+    counter: &mut usize,
+    f: &mut impl Write,
+) -> std::io::Result<()> {
+    write!(
+        f,
+        r#"@stdin = external global ptr, align 8
+@stdout = external global ptr, align 8
 
-#[derive(Debug)]
-enum Expression {
-    Plus,
-    Minus,
-    Right,
-    Left,
-    Comma,
-    Dot,
-    Conditional(Ast),
-}
+define i8 @main() {{
+  %arr = alloca ptr, align 8
+  %tape = alloca ptr, align 8
+  %1 = call noalias ptr @calloc(i64 noundef {0}, i64 noundef {1}) #3
+  %2 = getelementptr inbounds i{2}, ptr %1, i64 {3}
+  store ptr %2, ptr %arr, align 8
+  store ptr %1, ptr %tape, align 8
+"#,
+        options.tape_size,
+        options.cell_width.bytes(),
+        options.cell_width.bits(),
+        options.tape_origin
+    )?;
 
-#[derive(Debug)]
-struct Ast(pub Vec<Expression>);
-
-fn generate_ast_vec(tokens: &mut VecDeque<Opcode>) -> Result<Ast, String> {
-    let mut ast = vec![];
-
-    while let Some(opcode) = tokens.pop_front() {
-        let expr = match opcode {
-            Opcode::Plus => Expression::Plus,
-            Opcode::Minus => Expression::Minus,
-            Opcode::Rigth => Expression::Right,
-            Opcode::Left => Expression::Left,
-            Opcode::Comma => Expression::Comma,
-            Opcode::Dot => Expression::Dot,
-            Opcode::OpenBracket => {
-                let inner = generate_ast_vec(tokens)?;
-                match tokens.pop_front() {
-                    Some(Opcode::CloseBracket) => Expression::Conditional(inner),
-                    _ => return Err("missing closing bracket".into()),
-                }
-            }
-            Opcode::CloseBracket => {
-                tokens.push_front(Opcode::CloseBracket);
-                return Ok(Ast(ast));
-            }
-        };
+    *counter += 3;
 
-        ast.push(expr);
+    // a growing tape is reallocated, its size in bytes goes with it
+    if options.bounds == Bounds::Grow {
+        writeln!(f, "  %size = alloca i64, align 8")?;
+        writeln!(
+            f,
+            "  store i64 {}, ptr %size, align 8",
+            options.tape_size * options.cell_width.bytes()
+        )?;
     }
 
-    Ok(Ast(ast))
-}
-
-fn generate_ast(tokens: &mut VecDeque<Opcode>) -> Result<Ast, String> {
-    let block = generate_ast_vec(tokens)?;
+    // stdout is buffered by libc otherwise
+    if options.unbuffered {
+        write!(
+            f,
+            r#"  %{0} = load ptr, ptr @stdout, align 8
+  %{1} = call i32 @setvbuf(ptr noundef %{0}, ptr noundef null, i32 noundef 2, i64 noundef 0)
+"#,
+            *counter,
+            *counter + 1
+        )?;
 
-    if tokens.is_empty() {
-        Ok(block)
-    } else {
-        Err("Extra character in input".into())
+        *counter += 2;
     }
+
+    Ok(())
 }
 
-fn codegen_header(counter: &mut usize, f: &mut impl Write) -> std::io::Result<()> {
+/// Function printing the head position and exiting, called by trapping bound
+/// checks with the offset of the head in bytes.
+fn codegen_bounds_error(options: &Options, f: &mut impl Write) -> std::io::Result<()> {
     write!(
         f,
-        r#"@stdin = external global ptr, align 8
-@stdout = external global ptr, align 8
+        r#"
+@stderr = external global ptr, align 8
+@bounds_format = private unnamed_addr constant [44 x i8] c"error: tape head out of bounds at cell %ld\0A\00", align 1
+
+define internal void @bounds_error(i64 %offset) noreturn {{
+  %1 = sdiv exact i64 %offset, {1}
+  %2 = sub i64 %1, {0}
+  %3 = load ptr, ptr @stdout, align 8
+  %4 = call i32 @fflush(ptr noundef %3)
+  %5 = load ptr, ptr @stderr, align 8
+  %6 = call i32 (ptr, ptr, ...) @fprintf(ptr noundef %5, ptr noundef @bounds_format, i64 noundef %2)
+  call void @exit(i32 noundef 1) #4
+  unreachable
+}}
 
-define i8 @main() {{
-  %arr = alloca ptr, align 8
-  %1 = call noalias ptr @calloc(i64 noundef 0, i64 noundef 1000) #3
-  store ptr %1, ptr %arr, align 8
-"#
-    )?;
+declare i32 @fprintf(ptr noundef, ptr noundef, ...) #2
+declare void @exit(i32 noundef) #2
 
-    *counter += 2;
+attributes #4 = {{ noreturn nounwind }}
+"#,
+        options.tape_origin,
+        options.cell_width.bytes()
+    )
+}
 
-    Ok(())
+/// Function reallocating a growing tape to hold the byte at `offset` from its
+/// start, doubling its size and zeroing the new part. A negative offset is an
+/// error, the tape only grows to the right.
+fn codegen_grow_tape(f: &mut impl Write) -> std::io::Result<()> {
+    write!(
+        f,
+        r#"
+@grow_format = private unnamed_addr constant [28 x i8] c"error: cannot grow the tape\0A", align 1
+
+define internal void @grow_tape(ptr %tape, ptr %size, i64 %offset) {{
+  %1 = icmp slt i64 %offset, 0
+  br i1 %1, label %negative, label %grow
+negative:
+  call void @bounds_error(i64 %offset)
+  unreachable
+grow:
+  %2 = load i64, ptr %size, align 8
+  br label %double
+double:
+  %3 = phi i64 [ %2, %grow ], [ %4, %double ]
+  %4 = shl i64 %3, 1
+  %5 = icmp ugt i64 %4, %offset
+  br i1 %5, label %resize, label %double
+resize:
+  %6 = load ptr, ptr %tape, align 8
+  %7 = call ptr @realloc(ptr noundef %6, i64 noundef %4)
+  %8 = icmp eq ptr %7, null
+  br i1 %8, label %failed, label %zero
+failed:
+  %9 = load ptr, ptr @stderr, align 8
+  %10 = call i32 (ptr, ptr, ...) @fprintf(ptr noundef %9, ptr noundef @grow_format)
+  call void @exit(i32 noundef 1) #4
+  unreachable
+zero:
+  %11 = getelementptr inbounds i8, ptr %7, i64 %2
+  %12 = sub i64 %4, %2
+  call void @llvm.memset.p0.i64(ptr %11, i8 0, i64 %12, i1 false)
+  store ptr %7, ptr %tape, align 8
+  store i64 %4, ptr %size, align 8
+  ret void
+}}
+
+declare ptr @realloc(ptr noundef, i64 noundef) #2
+declare void @llvm.memset.p0.i64(ptr, i8, i64, i1)
+"#
+    )
 }
 
 fn codegen_end(counter: &mut usize, f: &mut impl Write) -> std::io::Result<()> {
@@ -116,7 +154,9 @@ fn codegen_end(counter: &mut usize, f: &mut impl Write) -> std::io::Result<()> {
 
 declare noalias ptr @calloc(i64 noundef, i64 noundef) #1
 declare i32 @putc(i8 noundef, ptr noundef) #2
-declare i8 @getc(ptr noundef) #2
+declare i32 @getc(ptr noundef) #2
+declare i32 @fflush(ptr noundef) #2
+declare i32 @setvbuf(ptr noundef, ptr noundef, i32 noundef, i64 noundef) #2
 
 attributes #0 = {{ noinline nounwind optnone sspstrong uwtable "frame-pointer"="all" "min-legal-vector-width"="0" "no-trapping-math"="true" "stack-protector-buffer-size"="8" "target-cpu"="x86-64" "target-features"="+cmov,+cx8,+fxsr,+mmx,+sse,+sse2,+x87" "tune-cpu"="generic" }}
 attributes #1 = {{ nounwind allocsize(0,1) "frame-pointer"="all" "no-trapping-math"="true" "stack-protector-buffer-size"="8" "target-cpu"="x86-64" "target-features"="+cmov,+cx8,+fxsr,+mmx,+sse,+sse2,+x87" "tune-cpu"="generic" }}
@@ -132,122 +172,304 @@ attributes #3 = {{ nounwind allocsize(0,1) }}
     Ok(())
 }
 
-fn codegen_ast(
-    ast: Ast,
+/// Moves the head by `v` cells, keeping it on the tape as `options.bounds`
+/// says.
+fn codegen_checked_step(
+    v: i32,
+    options: &Options,
+    counter: &mut usize,
+    f: &mut impl Write,
+) -> std::io::Result<()> {
+    let bytes = options.cell_width.bytes() as i64;
+    let size = options.tape_size as i64 * bytes;
+    let c = *counter;
+
+    // the head is followed by its offset in bytes from the start of the tape
+    write!(
+        f,
+        r#"
+  %{0} = load ptr, ptr %arr, align 8
+  %{1} = load ptr, ptr %tape, align 8
+  %{2} = ptrtoint ptr %{0} to i64
+  %{3} = ptrtoint ptr %{1} to i64
+  %{4} = sub i64 %{2}, %{3}
+"#,
+        c,
+        c + 1,
+        c + 2,
+        c + 3,
+        c + 4
+    )?;
+
+    if options.bounds == Bounds::Grow {
+        // the tape may move when it grows, the head is found again from its
+        // offset
+        write!(
+            f,
+            r#"  %{0} = add i64 %{1}, {2}
+  %{3} = load i64, ptr %size, align 8
+  %{4} = icmp uge i64 %{0}, %{3}
+  br i1 %{4}, label %grow_{5}, label %in_bounds_{5}
+grow_{5}:
+  call void @grow_tape(ptr %tape, ptr %size, i64 %{0})
+  br label %in_bounds_{5}
+in_bounds_{5}:
+  %{6} = load ptr, ptr %tape, align 8
+  %{7} = getelementptr inbounds i8, ptr %{6}, i64 %{0}
+  store ptr %{7}, ptr %arr, align 8
+"#,
+            c + 5,
+            c + 4,
+            v as i64 * bytes,
+            c + 6,
+            c + 7,
+            c,
+            c + 8,
+            c + 9
+        )?;
+
+        *counter += 10;
+    } else if options.bounds == Bounds::Wrap {
+        // the head is on the tape and moves forward less than its size, one
+        // subtraction brings it back
+        write!(
+            f,
+            r#"  %{0} = add i64 %{1}, {2}
+  %{3} = icmp uge i64 %{0}, {4}
+  %{5} = sub i64 %{0}, {4}
+  %{6} = select i1 %{3}, i64 %{5}, i64 %{0}
+  %{7} = getelementptr inbounds i8, ptr %{8}, i64 %{6}
+  store ptr %{7}, ptr %arr, align 8
+"#,
+            c + 5,
+            c + 4,
+            (v as i64 * bytes).rem_euclid(size),
+            c + 6,
+            size,
+            c + 7,
+            c + 8,
+            c + 9,
+            c + 1
+        )?;
+
+        *counter += 10;
+    } else {
+        // a negative index is above the size as unsigned
+        write!(
+            f,
+            r#"  %{0} = add i64 %{1}, {2}
+  %{3} = icmp uge i64 %{0}, {4}
+  br i1 %{3}, label %out_of_bounds_{5}, label %in_bounds_{5}
+out_of_bounds_{5}:
+  call void @bounds_error(i64 %{0})
+  unreachable
+in_bounds_{5}:
+  %{6} = getelementptr inbounds i8, ptr %{7}, i64 %{0}
+  store ptr %{6}, ptr %arr, align 8
+"#,
+            c + 5,
+            c + 4,
+            v as i64 * bytes,
+            c + 6,
+            size,
+            c,
+            c + 7,
+            c + 1
+        )?;
+
+        *counter += 8;
+    }
+
+    Ok(())
+}
+
+/// Reads a byte into the cell with `getc`, or stores the value `options.eof`
+/// asks for when it returns EOF, after flushing the output.
+fn codegen_input(
+    options: &Options,
+    counter: &mut usize,
+    f: &mut impl Write,
+) -> std::io::Result<()> {
+    let ty = format!("i{}", options.cell_width.bits());
+    let align = options.cell_width.bytes();
+
+    // what the program wrote so far may be a prompt for this input
+    if !options.unbuffered {
+        write!(
+            f,
+            r#"
+  %{0} = load ptr, ptr @stdout, align 8
+  %{1} = call i32 @fflush(ptr noundef %{0})"#,
+            *counter,
+            *counter + 1
+        )?;
+
+        *counter += 2;
+    }
+
+    let c = *counter;
+
+    write!(
+        f,
+        r#"
+  %{0} = load ptr, ptr %arr, align 8
+  %{1} = load ptr, ptr @stdin, align 8
+  %{2} = call i32 @getc(ptr noundef %{1})
+  %{3} = icmp eq i32 %{2}, -1
+"#,
+        c,
+        c + 1,
+        c + 2,
+        c + 3
+    )?;
+
+    *counter += 4;
+
+    // a byte read fills the whole cell
+    let byte = match options.cell_width {
+        CellWidth::Bits32 => format!("%{}", c + 2),
+        width => {
+            let cast = if width == CellWidth::Bits64 {
+                "zext"
+            } else {
+                "trunc"
+            };
+            writeln!(f, "  %{} = {cast} i32 %{} to {ty}", *counter, c + 2)?;
+            *counter += 1;
+            format!("%{}", *counter - 1)
+        }
+    };
+
+    let eof = match options.eof {
+        Eof::Zero => "0".to_string(),
+        Eof::MinusOne => "-1".to_string(),
+        Eof::Unchanged => {
+            writeln!(f, "  %{} = load {ty}, ptr %{c}, align {align}", *counter)?;
+            *counter += 1;
+            format!("%{}", *counter - 1)
+        }
+    };
+
+    write!(
+        f,
+        r#"  %{0} = select i1 %{1}, {ty} {eof}, {ty} {byte}
+  store {ty} %{0}, ptr %{2}, align {align}
+"#,
+        *counter,
+        c + 3,
+        c
+    )?;
+
+    *counter += 1;
+
+    Ok(())
+}
+
+fn codegen_ir(
+    ir: IR,
+    options: &Options,
     counter: &mut usize,
-    call_stack: &mut usize,
     f: &mut impl Write,
 ) -> std::io::Result<()> {
-    for expr in ast.0 {
-        match expr {
-            Expression::Plus => {
+    let ty = format!("i{}", options.cell_width.bits());
+    let align = options.cell_width.bytes();
+
+    for expr in ir {
+        match expr.node {
+            IRExpr::Set(v) => {
                 write!(
                     f,
                     r#"
   %{0} = load ptr, ptr %arr, align 8
-  %{1} = load i8, ptr %{0}, align 1
-  %{2} = add nsw i8 %{1}, 1
-  store i8 %{2}, ptr %{0}, align 1
+  store {ty} {1}, ptr %{0}, align {align}
 "#,
-                    *counter,
-                    *counter + 1,
-                    *counter + 2
+                    *counter, v
                 )?;
 
-                *counter += 3;
+                *counter += 1;
             }
-            Expression::Minus => {
+            IRExpr::Update(v) => {
                 write!(
                     f,
                     r#"
   %{0} = load ptr, ptr %arr, align 8
-  %{1} = load i8, ptr %{0}, align 1
-  %{2} = sub nsw i8 %{1}, 1
-  store i8 %{2}, ptr %{0}, align 1
+  %{1} = load {ty}, ptr %{0}, align {align}
+  %{2} = add {ty} %{1}, {3}
+  store {ty} %{2}, ptr %{0}, align {align}
 "#,
                     *counter,
                     *counter + 1,
-                    *counter + 2
+                    *counter + 2,
+                    v
                 )?;
 
                 *counter += 3;
             }
-            Expression::Right => {
+            // the IR has no checks for a growing tape, every step may grow it
+            IRExpr::Step(v) if options.bounds == Bounds::Grow => {
+                codegen_checked_step(v, options, counter, f)?
+            }
+            IRExpr::Step(v) => {
                 write!(
                     f,
                     r#"
   %{0} = load ptr, ptr %arr, align 8
-  %{1} = getelementptr inbounds i32, ptr %{0}, i32 1
+  %{1} = getelementptr inbounds {ty}, ptr %{0}, i64 {2}
   store ptr %{1}, ptr %arr, align 8
 "#,
                     *counter,
-                    *counter + 1
+                    *counter + 1,
+                    v
                 )?;
 
                 *counter += 2;
             }
-            Expression::Left => {
+            IRExpr::CheckedStep(v) => codegen_checked_step(v, options, counter, f)?,
+            IRExpr::Input => codegen_input(options, counter, f)?,
+            IRExpr::Output => {
                 write!(
                     f,
                     r#"
   %{0} = load ptr, ptr %arr, align 8
-  %{1} = getelementptr inbounds i32, ptr %{0}, i32 -1
-  store ptr %{1}, ptr %arr, align 8
+  %{1} = load {ty}, ptr %{0}, align {align}
 "#,
                     *counter,
                     *counter + 1
                 )?;
 
                 *counter += 2;
-            }
-            Expression::Comma => {
-                write!(
-                    f,
-                    r#"
-  %{0} = load ptr, ptr @stdin, align 8
-  %{1} = call i8 @getc(ptr noundef %{0})
+                let mut byte = *counter - 1;
 
-  %{2} = load ptr, ptr %arr, align 8
-  store i8 %{1}, ptr %{2}, align 1
-                    "#,
-                    *counter,
-                    *counter + 1,
-                    *counter + 2
-                )?;
+                // only the low byte of the cell is written
+                if options.cell_width != CellWidth::Bits8 {
+                    writeln!(f, "  %{} = trunc {ty} %{byte} to i8", *counter)?;
+                    byte = *counter;
+                    *counter += 1;
+                }
 
-                *counter += 3;
-            }
-            Expression::Dot => {
                 write!(
                     f,
-                    r#"
-  %{0} = load ptr, ptr %arr, align 8
-  %{1} = load i8, ptr %{0}, align 1
-  %{2} = load ptr, ptr @stdout, align 8
-  %{3} = call i32 @putc(i8 noundef %{1}, ptr noundef %{2})
-                    "#,
+                    r#"  %{0} = load ptr, ptr @stdout, align 8
+  %{1} = call i32 @putc(i8 noundef %{2}, ptr noundef %{0})
+"#,
                     *counter,
                     *counter + 1,
-                    *counter + 2,
-                    *counter + 3
+                    byte
                 )?;
 
-                *counter += 4;
+                *counter += 2;
             }
-            Expression::Conditional(inner) => {
-                let current = *call_stack;
-                *call_stack += 1;
-
+            IRExpr::ConditionalStart(id) => {
                 write!(
                     f,
                     r#"
-  br label %start_{current}
-start_{current}:
+  br label %start_{id}
+start_{id}:
   %{0} = load ptr, ptr %arr, align 8
-  %{1} = load i8, ptr %{0}, align 1
-  %{2} = icmp ne i8 %{1}, 0
-  br i1 %{2}, label %loop_{current}, label %end_{current}
-loop_{current}:
+  %{1} = load {ty}, ptr %{0}, align {align}
+  %{2} = icmp ne {ty} %{1}, 0
+  br i1 %{2}, label %loop_{id}, label %end_{id}
+loop_{id}:
 "#,
                     *counter,
                     *counter + 1,
@@ -255,14 +477,13 @@ loop_{current}:
                 )?;
 
                 *counter += 3;
-
-                codegen_ast(inner, counter, call_stack, f)?;
-
+            }
+            IRExpr::ConditionalEnd(id) => {
                 write!(
                     f,
                     r#"
-  br label %start_{current}
-end_{current}:
+  br label %start_{id}
+end_{id}:
 "#,
                 )?;
             }
@@ -272,36 +493,188 @@ end_{current}:
     Ok(())
 }
 
-fn codegen(ast: Ast, f: &mut impl Write) -> std::io::Result<()> {
+fn codegen(ir: IR, options: &Options, f: &mut impl Write) -> std::io::Result<()> {
     let mut counter = 0;
-    let mut call_stack = 0;
 
-    codegen_header(&mut counter, f)?;
-    codegen_ast(ast, &mut counter, &mut call_stack, f)?;
+    codegen_header(options, &mut counter, f)?;
+    codegen_ir(ir, options, &mut counter, f)?;
     codegen_end(&mut counter, f)?;
 
+    if let Bounds::Trap | Bounds::Grow = options.bounds {
+        codegen_bounds_error(options, f)?;
+    }
+
+    if options.bounds == Bounds::Grow {
+        codegen_grow_tape(f)?;
+    }
+
     Ok(())
 }
 
-pub fn compile(input: String) -> Result<(), String> {
-    let mut opcodes = input
-        .chars()
-        .filter(|c| !c.is_whitespace())
-        .filter_map(parse)
-        .collect::<VecDeque<_>>();
+/// Writes the LLVM IR of `ast`, after running the optimizations of
+/// `options`, into `f`.
+pub fn compile(ast: Ast, options: &Options, f: &mut impl Write) -> Result<(), Error> {
+    let ir = ir::lower(ast, options);
 
-    let exprs = generate_ast(&mut opcodes)?;
+    codegen(ir, options, f).map_err(Error::io("llvm output"))?;
+    f.flush().map_err(Error::io("llvm output"))?;
 
-    let file = File::create("code.ll").unwrap();
-    let mut f = BufWriter::new(file);
-    codegen(exprs, &mut f).unwrap();
-    f.flush().unwrap();
-    drop(f);
+    Ok(())
+}
 
-    Command::new("llc")
-        .args(["code.ll"])
-        .status()
-        .map_err(|e| format!("llc: {e:?}"))?;
+pub fn compile_to_string(ast: Ast, options: &Options) -> Result<String, Error> {
+    let mut out = vec![];
+    compile(ast, options, &mut out)?;
 
-    Ok(())
+    Ok(String::from_utf8(out).expect("generated llvm ir is ascii"))
+}
+
+pub struct LlvmBackend;
+
+impl Backend for LlvmBackend {
+    fn name(&self) -> &'static str {
+        "llvm"
+    }
+
+    fn extension(&self) -> &'static str {
+        "ll"
+    }
+
+    fn emit(&self, ast: Ast, options: &Options, mut f: &mut dyn Write) -> Result<(), Error> {
+        compile(ast, options, &mut f)
+    }
+
+    fn emit_asm(&self, ast: Ast, options: &Options, f: &mut dyn Write) -> Result<(), Error> {
+        let code = compile_to_string(ast, options)?;
+
+        let mut llc = Command::new("llc")
+            .args(["-filetype=asm", "-relocation-model=pic", "-o", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| Error::toolchain("llc", e.to_string()))?;
+
+        if let Some(mut stdin) = llc.stdin.take() {
+            stdin
+                .write_all(code.as_bytes())
+                .map_err(Error::io("stdin of llc"))?;
+        }
+
+        let output = llc
+            .wait_with_output()
+            .map_err(|e| Error::toolchain("llc", e.to_string()))?;
+        check_tool("llc", &output)?;
+
+        f.write_all(&output.stdout)
+            .map_err(Error::io("assembly output"))?;
+
+        Ok(())
+    }
+
+    fn assemble(&self, source: &Path, object: &Path) -> Result<(), Error> {
+        run_tool(
+            Command::new("llc")
+                .args(["-filetype=obj", "-relocation-model=pic", "-o"])
+                .arg(object)
+                .arg(source),
+        )
+    }
+
+    fn link(&self, object: &Path, executable: &Path) -> Result<(), Error> {
+        run_tool(Command::new("gcc").arg("-o").arg(executable).arg(object))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use frontend::{parse_source, Bounds, CellWidth, Eof, OptLevel, Options};
+
+    use crate::compile_to_string;
+
+    #[test]
+    fn growing_tape_is_reallocated() {
+        let options = Options {
+            opt_level: OptLevel::O1,
+            bounds: Bounds::Grow,
+            tape_size: 100,
+            ..Options::default()
+        };
+        let code = compile_to_string(parse_source("+[>+]<<").unwrap(), &options).unwrap();
+
+        assert!(code.contains("  store i64 100, ptr %size, align 8\n"));
+        assert!(code.contains("define internal void @grow_tape("));
+        assert!(code.contains("define internal void @bounds_error("));
+        // every step may grow the tape, the IR has no checks for it
+        assert_eq!(code.matches("call void @grow_tape(ptr %tape").count(), 2);
+    }
+
+    #[test]
+    fn head_starts_at_the_origin() {
+        let options = Options {
+            bounds: Bounds::Trap,
+            tape_size: 10,
+            tape_origin: 5,
+            cell_width: CellWidth::Bits16,
+            ..Options::default()
+        };
+        let code = compile_to_string(parse_source("<").unwrap(), &options).unwrap();
+
+        assert!(
+            code.contains("  %1 = call noalias ptr @calloc(i64 noundef 10, i64 noundef 2) #3\n")
+        );
+        assert!(code.contains("  %2 = getelementptr inbounds i16, ptr %1, i64 5\n"));
+        // the error shows the cell counted from the origin
+        assert!(code.contains("  %1 = sdiv exact i64 %offset, 2\n  %2 = sub i64 %1, 5\n"));
+    }
+
+    #[test]
+    fn cell_widths() {
+        let code = |cell_width| {
+            let options = Options {
+                cell_width,
+                ..Options::default()
+            };
+            compile_to_string(parse_source(",+.").unwrap(), &options).unwrap()
+        };
+
+        let byte = code(CellWidth::Bits8);
+        assert!(byte.contains(" = trunc i32 %7 to i8\n"));
+        assert!(byte.contains(" = add i8 %"));
+        assert!(!byte.contains(" = trunc i8 %"));
+
+        // reads fill the whole cell, writes take its low byte
+        let word = code(CellWidth::Bits16);
+        assert!(word.contains(" = trunc i32 %7 to i16\n"));
+        assert!(word.contains(" = add i16 %"));
+        assert!(word.contains(" = trunc i16 %"));
+
+        let quad = code(CellWidth::Bits64);
+        assert!(quad.contains(" = zext i32 %7 to i64\n"));
+        assert!(quad.contains(" = add i64 %"));
+        assert!(quad.contains(" = trunc i64 %"));
+    }
+
+    #[test]
+    fn end_of_input() {
+        let code = |eof| {
+            let options = Options {
+                eof,
+                ..Options::default()
+            };
+            compile_to_string(parse_source(",").unwrap(), &options).unwrap()
+        };
+
+        // getc returns -1 at the end of the input, the byte read is replaced
+        let read = "  %8 = icmp eq i32 %7, -1\n  %9 = trunc i32 %7 to i8\n";
+        assert!(code(Eof::Zero).contains(&format!(
+            "{read}  %10 = select i1 %8, i8 0, i8 %9\n  store i8 %10, ptr %5, align 1\n"
+        )));
+        assert!(code(Eof::MinusOne).contains(&format!(
+            "{read}  %10 = select i1 %8, i8 -1, i8 %9\n  store i8 %10, ptr %5, align 1\n"
+        )));
+        assert!(code(Eof::Unchanged).contains(&format!(
+            "{read}  %10 = load i8, ptr %5, align 1\n  %11 = select i1 %8, i8 %10, i8 %9\n"
+        )));
+    }
 }
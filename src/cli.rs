@@ -0,0 +1,594 @@
+use std::{fs::read_to_string, path::PathBuf, str::FromStr};
+
+use frontend::{Error, Options};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Subcommand {
+    Check,
+    Build,
+    Run,
+    Emit,
+    Debug,
+}
+
+impl Subcommand {
+    fn name(&self) -> &'static str {
+        match self {
+            Subcommand::Check => "check",
+            Subcommand::Build => "build",
+            Subcommand::Run => "run",
+            Subcommand::Emit => "emit",
+            Subcommand::Debug => "debug",
+        }
+    }
+
+    fn about(&self) -> &'static str {
+        match self {
+            Subcommand::Check => "Parse the program and report its errors",
+            Subcommand::Build => "Compile the program to an executable",
+            Subcommand::Run => "Compile the program and run it",
+            Subcommand::Emit => "Write an intermediate representation of the program",
+            Subcommand::Debug => "Step through the program in an interactive debugger",
+        }
+    }
+}
+
+const SUBCOMMANDS: &[Subcommand] = &[
+    Subcommand::Check,
+    Subcommand::Build,
+    Subcommand::Run,
+    Subcommand::Emit,
+    Subcommand::Debug,
+];
+
+/// What `emit` writes out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Emit {
+    Ast,
+    Ir,
+    Asm,
+    Llvm,
+    Obj,
+}
+
+impl FromStr for Emit {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ast" => Ok(Emit::Ast),
+            "ir" => Ok(Emit::Ir),
+            "asm" => Ok(Emit::Asm),
+            "llvm" => Ok(Emit::Llvm),
+            "obj" => Ok(Emit::Obj),
+            _ => Err(Error::Usage(format!(
+                "unknown emit kind {s:?}, expected one of: ast, ir, asm, llvm, obj"
+            ))),
+        }
+    }
+}
+
+pub enum Program {
+    File(PathBuf),
+    Inline(String),
+}
+
+impl Program {
+    /// Name used in diagnostics and the source code of the program.
+    pub fn load(&self) -> Result<(String, String), Error> {
+        match self {
+            Program::File(path) => {
+                let name = path.display().to_string();
+                let data = read_to_string(path).map_err(Error::io(name.clone()))?;
+                Ok((name, data))
+            }
+            Program::Inline(data) => Ok(("<input>".into(), data.clone())),
+        }
+    }
+}
+
+/// Where the compiled program reads its input from when it is run.
+pub enum Input {
+    Inherit,
+    File(PathBuf),
+    String(String),
+}
+
+pub struct Cli {
+    pub subcommand: Subcommand,
+    pub program: Program,
+    pub backend: String,
+    pub options: Options,
+    pub output: Option<PathBuf>,
+    pub emit: Emit,
+    pub input: Input,
+    /// Byte sent to the program once its input is exhausted.
+    pub eof_marker: Option<u8>,
+    pub keep_temps: bool,
+}
+
+pub enum Parsed {
+    Cli(Cli),
+    Help(String),
+}
+
+struct Flag {
+    names: &'static [&'static str],
+    value: Option<&'static str>,
+    help: &'static str,
+    subcommands: &'static [Subcommand],
+}
+
+const COMPILE: &[Subcommand] = &[Subcommand::Build, Subcommand::Run, Subcommand::Emit];
+
+/// Subcommands which lay out a tape, compiled or interpreted.
+const TAPE: &[Subcommand] = &[
+    Subcommand::Build,
+    Subcommand::Run,
+    Subcommand::Emit,
+    Subcommand::Debug,
+];
+
+const INPUT: &[Subcommand] = &[Subcommand::Run, Subcommand::Debug];
+
+const FLAGS: &[Flag] = &[
+    Flag {
+        names: &["-e"],
+        value: Some("PROGRAM"),
+        help: "Program given on the command line instead of a file",
+        subcommands: SUBCOMMANDS,
+    },
+    Flag {
+        names: &["--backend"],
+        value: Some("NAME"),
+        help: "Code generator or interpreter to use [default: ir, interp for unbounded cells]",
+        subcommands: COMPILE,
+    },
+    Flag {
+        names: &["-O"],
+        value: Some("LEVEL"),
+        help: "Optimization level: 0, 1, 2, 3 or s [default: 0]",
+        subcommands: COMPILE,
+    },
+    Flag {
+        names: &["--tape-size"],
+        value: Some("CELLS"),
+        help: "Number of cells on the tape [default: 30000]",
+        subcommands: TAPE,
+    },
+    Flag {
+        names: &["--tape-origin"],
+        value: Some("CELL"),
+        help: "Cell the head starts on, or middle [default: 0]",
+        subcommands: TAPE,
+    },
+    Flag {
+        names: &["--cell-bits"],
+        value: Some("BITS"),
+        help: "Width of the tape cells: 8, 16, 32, 64 or unbounded [default: 8]",
+        subcommands: TAPE,
+    },
+    Flag {
+        names: &["--bounds"],
+        value: Some("MODE"),
+        help: "Tape bounds checking: unchecked, trap, wrap or grow [default: unchecked]",
+        subcommands: TAPE,
+    },
+    Flag {
+        names: &["--eof"],
+        value: Some("VALUE"),
+        help: "What `,` stores once the input is exhausted: zero, minus-one or unchanged [default: unchanged]",
+        subcommands: TAPE,
+    },
+    Flag {
+        names: &["--unbuffered"],
+        value: None,
+        help: "Write the output of every `.` at once, for interactive programs",
+        subcommands: COMPILE,
+    },
+    Flag {
+        names: &["-o", "--output"],
+        value: Some("PATH"),
+        help: "Where to write the executable or emitted file [default: code for build, stdout for emit]",
+        subcommands: COMPILE,
+    },
+    Flag {
+        names: &["--keep-temps"],
+        value: None,
+        help: "Keep the temporary directory holding the intermediate files",
+        subcommands: COMPILE,
+    },
+    Flag {
+        names: &["--emit"],
+        value: Some("KIND"),
+        help: "What to write: ast, ir, asm, llvm or obj [default: asm]",
+        subcommands: &[Subcommand::Emit],
+    },
+    Flag {
+        names: &["--input"],
+        value: Some("FILE"),
+        help: "Read the program input from a file [default: stdin, shared with the commands for debug]",
+        subcommands: INPUT,
+    },
+    Flag {
+        names: &["--input-string"],
+        value: Some("TEXT"),
+        help: "Use the given text as the program input",
+        subcommands: INPUT,
+    },
+    Flag {
+        names: &["--eof-marker"],
+        value: Some("BYTE"),
+        help: "Byte sent after the end of the input, e.g. 0 or 10",
+        subcommands: &[Subcommand::Run],
+    },
+    Flag {
+        names: &["-h", "--help"],
+        value: None,
+        help: "Print help",
+        subcommands: SUBCOMMANDS,
+    },
+];
+
+pub fn usage() -> String {
+    let mut out =
+        String::from("Usage: brainfuck <COMMAND> [OPTIONS] <FILE | -e PROGRAM>\n\nCommands:\n");
+
+    for subcommand in SUBCOMMANDS {
+        out += &format!("  {:<7}{}\n", subcommand.name(), subcommand.about());
+    }
+
+    out += "\nRun `brainfuck <COMMAND> --help` for the options of a command.\n";
+    out
+}
+
+fn help(subcommand: Subcommand) -> String {
+    let mut out = format!(
+        "{}\n\nUsage: brainfuck {} [OPTIONS] <FILE | -e PROGRAM>\n\nOptions:\n",
+        subcommand.about(),
+        subcommand.name()
+    );
+
+    let flags = FLAGS
+        .iter()
+        .filter(|flag| flag.subcommands.contains(&subcommand))
+        .map(|flag| {
+            let names = flag.names.join(", ");
+            match flag.value {
+                Some(value) => (format!("{names} <{value}>"), flag.help),
+                None => (names, flag.help),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let width = flags
+        .iter()
+        .map(|(names, _)| names.len())
+        .max()
+        .unwrap_or(0);
+
+    for (names, help) in flags {
+        out += &format!("  {names:<width$}  {help}\n");
+    }
+
+    out
+}
+
+/// Largest tape in bytes: native code reserves it in `.bss` and addresses it
+/// with 32 bit displacements and immediates.
+const MAX_TAPE_BYTES: usize = i32::MAX as usize;
+
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Parsed, Error> {
+    let mut args = args.into_iter();
+
+    let subcommand = match args.next().as_deref() {
+        None | Some("-h" | "--help" | "help") => return Ok(Parsed::Help(usage())),
+        Some(name) => SUBCOMMANDS
+            .iter()
+            .copied()
+            .find(|subcommand| subcommand.name() == name)
+            .ok_or_else(|| Error::Usage(format!("unknown command {name:?}\n\n{}", usage())))?,
+    };
+
+    let mut program = None;
+    let mut backend = None;
+    let mut origin = None;
+    let mut cli = Cli {
+        subcommand,
+        program: Program::Inline(String::new()),
+        backend: String::new(),
+        options: Options::default(),
+        output: None,
+        emit: Emit::Asm,
+        input: Input::Inherit,
+        eof_marker: None,
+        keep_temps: false,
+    };
+
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') {
+            if program.is_some() {
+                return Err(Error::Usage(format!("unexpected argument {arg:?}")));
+            }
+            program = Some(Program::File(arg.into()));
+            continue;
+        }
+
+        let (name, inline) = match arg.split_once('=') {
+            Some((name, value)) if arg.starts_with("--") => (name, Some(value.to_string())),
+            _ if arg.starts_with("-O") && arg.len() > 2 => ("-O", Some(arg[2..].to_string())),
+            _ => (arg.as_str(), None),
+        };
+
+        let flag = FLAGS
+            .iter()
+            .find(|flag| flag.names.contains(&name) && flag.subcommands.contains(&subcommand))
+            .ok_or_else(|| {
+                Error::Usage(format!(
+                    "unknown option {name:?} for `{}`, see `brainfuck {} --help`",
+                    subcommand.name(),
+                    subcommand.name()
+                ))
+            })?;
+
+        let value = match (flag.value, inline) {
+            (Some(_), Some(value)) => value,
+            (Some(_), None) => args
+                .next()
+                .ok_or_else(|| Error::Usage(format!("missing value after {name:?}")))?,
+            (None, Some(_)) => return Err(Error::Usage(format!("{name:?} does not take a value"))),
+            (None, None) => String::new(),
+        };
+
+        match flag.names[0] {
+            "-h" => return Ok(Parsed::Help(help(subcommand))),
+            "-e" => {
+                if program.is_some() {
+                    return Err(Error::Usage("more than one program given".into()));
+                }
+                program = Some(Program::Inline(value));
+            }
+            "--backend" => backend = Some(value),
+            "-O" => cli.options.opt_level = value.parse()?,
+            "--tape-size" => {
+                // generated code compares the head with the size as a 32 bit
+                // immediate
+                cli.options.tape_size = value
+                    .parse()
+                    .ok()
+                    .filter(|size| (1..=i32::MAX as usize).contains(size))
+                    .ok_or_else(|| Error::Usage(format!("invalid tape size {value:?}")))?;
+            }
+            "--tape-origin" => origin = Some(value),
+            "--cell-bits" if value == "unbounded" => cli.options.unbounded = true,
+            "--cell-bits" => cli.options.cell_width = value.parse()?,
+            "--bounds" => cli.options.bounds = value.parse()?,
+            "--eof" => cli.options.eof = value.parse()?,
+            "--unbuffered" => cli.options.unbuffered = true,
+            "-o" => cli.output = Some(value.into()),
+            "--emit" => cli.emit = value.parse()?,
+            "--keep-temps" => cli.keep_temps = true,
+            "--input" | "--input-string" => {
+                if !matches!(cli.input, Input::Inherit) {
+                    return Err(Error::Usage("more than one input given".into()));
+                }
+                cli.input = match name {
+                    "--input" => Input::File(value.into()),
+                    _ => Input::String(value),
+                };
+            }
+            "--eof-marker" => {
+                let byte = value.parse().map_err(|_| {
+                    Error::Usage(format!("invalid eof marker {value:?}, expected 0 to 255"))
+                })?;
+                cli.eof_marker = Some(byte);
+            }
+            _ => unreachable!("every flag is handled"),
+        }
+    }
+
+    cli.program = program.ok_or_else(|| {
+        Error::Usage(format!(
+            "missing program, see `brainfuck {} --help`",
+            subcommand.name()
+        ))
+    })?;
+
+    if cli.options.unbounded && !INPUT.contains(&subcommand) {
+        return Err(Error::Usage(
+            "unbounded cells are only supported by `run` and `debug`, which interpret the program"
+                .into(),
+        ));
+    }
+
+    // only the interpreter supports unbounded cells
+    let default = if cli.options.unbounded {
+        "interp"
+    } else {
+        "ir"
+    };
+    cli.backend = backend.unwrap_or_else(|| default.into());
+
+    // the width of the cells may come after the size
+    let bytes = cli.options.tape_size as u128 * cli.options.cell_width.bytes() as u128;
+    if !cli.options.unbounded && bytes > MAX_TAPE_BYTES as u128 {
+        return Err(Error::Usage(format!(
+            "a tape of {} cells of {} bits takes {bytes} bytes, more than the {MAX_TAPE_BYTES} \
+             the generated code can address",
+            cli.options.tape_size,
+            cli.options.cell_width.bits()
+        )));
+    }
+
+    // the origin depends on the tape size, which may come after it
+    if let Some(value) = origin {
+        let size = cli.options.tape_size;
+        cli.options.tape_origin = match value.as_str() {
+            "middle" => size / 2,
+            _ => value
+                .parse()
+                .ok()
+                .filter(|origin| *origin < size)
+                .ok_or_else(|| {
+                    Error::Usage(format!(
+                        "invalid tape origin {value:?}, expected middle or a cell below {size}"
+                    ))
+                })?,
+        };
+    }
+
+    Ok(Parsed::Cli(cli))
+}
+
+#[cfg(test)]
+mod tests {
+    use frontend::{Bounds, CellWidth, Eof, OptLevel};
+
+    use super::{parse, Cli, Emit, Input, Parsed, Program, Subcommand};
+
+    fn parsed(args: &str) -> Cli {
+        match parse(args.split_whitespace().map(String::from)) {
+            Ok(Parsed::Cli(cli)) => cli,
+            Ok(Parsed::Help(_)) => panic!("{args:?} printed the help"),
+            Err(error) => panic!("{args:?} failed: {error}"),
+        }
+    }
+
+    fn error(args: &str) -> String {
+        match parse(args.split_whitespace().map(String::from)) {
+            Err(error) => error.to_string(),
+            Ok(_) => panic!("{args:?} was accepted"),
+        }
+    }
+
+    #[test]
+    fn subcommands_and_options() {
+        let cli = parsed("build -O2 --backend=llvm --tape-size 100 --bounds trap -o out code.bf");
+
+        assert_eq!(cli.subcommand, Subcommand::Build);
+        assert!(matches!(cli.program, Program::File(ref path) if path.to_str() == Some("code.bf")));
+        assert_eq!(cli.backend, "llvm");
+        assert_eq!(cli.options.opt_level, OptLevel::O2);
+        assert_eq!(cli.options.tape_size, 100);
+        assert_eq!(cli.options.bounds, Bounds::Trap);
+        assert_eq!(cli.output.as_deref().and_then(|p| p.to_str()), Some("out"));
+        assert!(!cli.keep_temps);
+        assert!(parsed("build --keep-temps -e +").keep_temps);
+
+        let cli = parsed("emit --emit ir -Os --cell-bits 32 --eof minus-one -e +.");
+        assert_eq!(cli.subcommand, Subcommand::Emit);
+        assert!(matches!(cli.program, Program::Inline(ref data) if data == "+."));
+        assert_eq!(cli.backend, "ir");
+        assert_eq!(cli.emit, Emit::Ir);
+        assert_eq!(cli.options.opt_level, OptLevel::Os);
+        assert_eq!(cli.options.cell_width, CellWidth::Bits32);
+        assert_eq!(cli.options.eof, Eof::MinusOne);
+
+        // the origin is read against the size given after it
+        let cli = parsed("run --tape-origin middle --tape-size 10 -e +");
+        assert_eq!(cli.options.tape_origin, 5);
+
+        let cli = parsed("run --cell-bits unbounded -e +");
+        assert!(cli.options.unbounded);
+        assert_eq!(cli.backend, "interp");
+    }
+
+    #[test]
+    fn help() {
+        for args in ["", "help", "--help"] {
+            let parsed = parse(args.split_whitespace().map(String::from));
+            assert!(matches!(parsed, Ok(Parsed::Help(help)) if help.contains("Commands:")));
+        }
+
+        let parsed = parse(["run", "-h"].map(String::from));
+        assert!(matches!(parsed, Ok(Parsed::Help(help)) if help.contains("--eof-marker")));
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        assert!(error("compile -e +").starts_with("unknown command \"compile\""));
+        assert_eq!(error("run"), "missing program, see `brainfuck run --help`");
+        assert_eq!(error("run -O4 -e +"), "unsupported optimization: \"-O4\"");
+        assert!(error("run --bounds maybe -e +").starts_with("unknown bounds mode \"maybe\""));
+        assert!(error("run --eof one -e +").starts_with("unknown eof behaviour \"one\""));
+        assert!(error("run --cell-bits 12 -e +").starts_with("unknown cell width \"12\""));
+        assert_eq!(error("run --tape-size 0 -e +"), "invalid tape size \"0\"");
+        assert_eq!(
+            error("build --tape-size 2147483647 --cell-bits 64 -e +"),
+            "a tape of 2147483647 cells of 64 bits takes 17179869176 bytes, more than the \
+             2147483647 the generated code can address"
+        );
+        assert_eq!(
+            parsed("build --cell-bits 16 --tape-size 1073741823 --tape-origin middle -e +")
+                .options
+                .tape_origin,
+            536870911
+        );
+        assert!(error("run --tape-origin 10 --tape-size 10 -e +")
+            .starts_with("invalid tape origin \"10\""));
+        assert_eq!(error("run -e + -e -"), "more than one program given");
+        assert_eq!(error("run a.bf b.bf"), "unexpected argument \"b.bf\"");
+        assert_eq!(error("run -e"), "missing value after \"-e\"");
+        assert_eq!(
+            error("run --unbuffered=yes -e +"),
+            "\"--unbuffered\" does not take a value"
+        );
+        assert_eq!(
+            error("check -O1 -e +"),
+            "unknown option \"-O\" for `check`, see `brainfuck check --help`"
+        );
+        assert_eq!(
+            error("emit --emit wasm -e +"),
+            "unknown emit kind \"wasm\", expected one of: ast, ir, asm, llvm, obj"
+        );
+        assert!(error("build --cell-bits unbounded -e +")
+            .starts_with("unbounded cells are only supported by `run` and `debug`"));
+    }
+
+    #[test]
+    fn program_input() {
+        let cli = parsed("run --input in.txt --eof-marker 10 -e ,.");
+        assert!(matches!(cli.input, Input::File(ref path) if path.to_str() == Some("in.txt")));
+        assert_eq!(cli.eof_marker, Some(10));
+
+        let cli = parsed("run --input-string=abc -e ,.");
+        assert!(matches!(cli.input, Input::String(ref text) if text == "abc"));
+        assert_eq!(cli.eof_marker, None);
+
+        assert!(matches!(parsed("run -e ,.").input, Input::Inherit));
+
+        assert_eq!(
+            error("run --input in.txt --input-string abc -e ,."),
+            "more than one input given"
+        );
+        assert_eq!(
+            error("run --eof-marker 256 -e ,."),
+            "invalid eof marker \"256\", expected 0 to 255"
+        );
+        assert_eq!(
+            error("build --input in.txt -e ,."),
+            "unknown option \"--input\" for `build`, see `brainfuck build --help`"
+        );
+    }
+
+    #[test]
+    fn debug_shares_the_input_and_tape_options() {
+        let cli = parsed(
+            "debug --input-string abc --tape-size 16 --tape-origin middle --cell-bits unbounded \
+             --bounds wrap --eof zero -e ,.",
+        );
+
+        assert_eq!(cli.subcommand, Subcommand::Debug);
+        assert!(matches!(cli.input, Input::String(ref text) if text == "abc"));
+        assert_eq!(cli.options.tape_size, 16);
+        assert_eq!(cli.options.tape_origin, 8);
+        assert!(cli.options.unbounded);
+        assert_eq!(cli.options.bounds, Bounds::Wrap);
+        assert_eq!(cli.options.eof, Eof::Zero);
+
+        // compiling and the end of input marker are for `run` only
+        for option in ["-O1", "--backend=ir", "--eof-marker=0", "--unbuffered"] {
+            assert!(error(&format!("debug {option} -e +")).starts_with("unknown option"));
+        }
+    }
+}
@@ -1,92 +1,375 @@
+mod cli;
+mod registry;
+mod temp;
+
 use std::{
-    fs::read_to_string,
-    io::{stdin, BufRead, Write},
-    process::{Command, Stdio},
+    fs::File,
+    io::{self, stdin, stdout, BufWriter, Cursor, ErrorKind, Read, Write},
+    os::unix::process::ExitStatusExt,
+    path::{Path, PathBuf},
+    process::{Command, ExitCode, ExitStatus, Stdio},
+    thread,
 };
 
-#[derive(PartialEq)]
-enum Optimization {
-    O0,
-    O1,
-    O2,
+use cli::{Cli, Emit, Input, Parsed, Subcommand};
+use frontend::{Ast, Backend, Error};
+use temp::TempDir;
+
+fn main() -> ExitCode {
+    let cli = match cli::parse(std::env::args().skip(1)) {
+        Ok(Parsed::Cli(cli)) => cli,
+        Ok(Parsed::Help(help)) => {
+            print!("{help}");
+            return ExitCode::SUCCESS;
+        }
+        Err(error) => return report(error, "", ""),
+    };
+
+    let (name, data) = match cli.program.load() {
+        Ok(program) => program,
+        Err(error) => return report(error, "", ""),
+    };
+
+    match execute(&cli, &data) {
+        Ok(code) => code,
+        Err(error) => report(error, &name, &data),
+    }
 }
 
-fn main() -> Result<(), String> {
-    let args = std::env::args().collect::<Vec<_>>();
+fn report(error: Error, name: &str, data: &str) -> ExitCode {
+    match error {
+        Error::Parse(diagnostics) => {
+            for diagnostic in diagnostics {
+                eprintln!("{}", diagnostic.render(name, data));
+            }
+        }
+        error => eprintln!("error: {error}"),
+    }
 
-    let data = match args.iter().position(|s| s == "-f") {
-        Some(index) => match args.get(index + 1) {
-            Some(path) => read_to_string(path).map_err(|_| format!("file {path:?} not found")),
-            None => Err("missing filename after \"-f\"".into()),
-        },
-        None => args.last().cloned().ok_or("missing input data".into()),
-    }?;
+    ExitCode::FAILURE
+}
 
-    let optimization = {
-        let optimizations = args
+fn find_backend(name: &str) -> Result<&'static dyn Backend, Error> {
+    registry::find(name).ok_or_else(|| {
+        let names = registry::BACKENDS
             .iter()
-            .filter(|arg| arg.starts_with("-O"))
-            .map(|arg| arg.as_ref())
+            .map(|backend| backend.name())
             .collect::<Vec<_>>();
+        Error::Usage(format!(
+            "unknown backend {name:?}, expected one of: {}",
+            names.join(", ")
+        ))
+    })
+}
 
-        match optimizations[..] {
-            [] => Ok(Optimization::O0),
-            ["-O0"] => Ok(Optimization::O0),
-            ["-O1"] => Ok(Optimization::O1),
-            ["-O2"] => Ok(Optimization::O2),
-            [opt] => Err(format!("unsuppoted optimization: {opt:?}")),
-            _ => Err("multiple optimization specified".to_string()),
+/// Runs the subcommand, returning the exit code of the compiled program for
+/// `run`.
+fn execute(cli: &Cli, data: &str) -> Result<ExitCode, Error> {
+    let backend = find_backend(&cli.backend)?;
+    let ast = frontend::parse_source(data).map_err(Error::Parse)?;
+
+    if cli.subcommand == Subcommand::Check {
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if cli.subcommand == Subcommand::Debug {
+        return debug(cli, data, &ast);
+    }
+
+    if cli.options.unbounded && !backend.interprets() {
+        return Err(Error::Usage(format!(
+            "the {} backend does not support unbounded cells",
+            backend.name()
+        )));
+    }
+
+    if cli.subcommand == Subcommand::Run && backend.interprets() {
+        return interpret(cli, backend, ast);
+    }
+
+    let temp = TempDir::new(cli.keep_temps).map_err(Error::io("temporary directory"))?;
+
+    match cli.subcommand {
+        Subcommand::Check | Subcommand::Debug => {
+            unreachable!("nothing to build for {:?}", cli.subcommand)
+        }
+        Subcommand::Build => {
+            let output = cli.output.as_deref().unwrap_or(Path::new("code"));
+            build(cli, backend, ast, temp.path(), output)?;
+        }
+        Subcommand::Run => {
+            let executable = match &cli.output {
+                // a bare file name would be looked up in PATH
+                Some(path) if path.is_relative() => Path::new(".").join(path),
+                Some(path) => path.clone(),
+                None => temp.path().join("code"),
+            };
+
+            build(cli, backend, ast, temp.path(), &executable)?;
+            return run(&executable, &cli.input, cli.eof_marker);
+        }
+        Subcommand::Emit => emit(cli, backend, ast, temp.path())?,
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Writes the artifact of `backend` in `dir` and returns where it was
+/// written.
+fn write_artifact(
+    cli: &Cli,
+    backend: &dyn Backend,
+    ast: Ast,
+    dir: &Path,
+) -> Result<PathBuf, Error> {
+    let source = dir.join("code").with_extension(backend.extension());
+    let context = source.display().to_string();
+
+    let file = File::create(&source).map_err(Error::io(&context))?;
+    let mut f = BufWriter::new(file);
+    backend.emit(ast, &cli.options, &mut f)?;
+    f.flush().map_err(Error::io(context))?;
+
+    Ok(source)
+}
+
+fn build(
+    cli: &Cli,
+    backend: &dyn Backend,
+    ast: Ast,
+    dir: &Path,
+    executable: &Path,
+) -> Result<(), Error> {
+    let source = write_artifact(cli, backend, ast, dir)?;
+    let object = dir.join("code.o");
+
+    backend.assemble(&source, &object)?;
+    backend.link(&object, executable)?;
+
+    Ok(())
+}
+
+fn emit(cli: &Cli, backend: &dyn Backend, ast: Ast, dir: &Path) -> Result<(), Error> {
+    if cli.emit == Emit::Obj {
+        let object = cli.output.as_deref().unwrap_or(Path::new("code.o"));
+        let source = write_artifact(cli, backend, ast, dir)?;
+        return backend.assemble(&source, object);
+    }
+
+    let (mut f, context): (Box<dyn Write>, String) = match &cli.output {
+        Some(path) => {
+            let context = path.display().to_string();
+            let file = File::create(path).map_err(Error::io(&context))?;
+            (Box::new(BufWriter::new(file)), context)
+        }
+        None => (Box::new(stdout().lock()), "stdout".into()),
+    };
+
+    match cli.emit {
+        Emit::Ast => write!(f, "{ast}").map_err(Error::io(&context))?,
+        Emit::Ir => {
+            let ir = ir::lower(ast, &cli.options);
+            ir::dump(&ir, &mut f).map_err(Error::io(&context))?;
+        }
+        Emit::Asm => backend.emit_asm(ast, &cli.options, &mut f)?,
+        Emit::Llvm if backend.extension() == "ll" => backend.emit(ast, &cli.options, &mut f)?,
+        Emit::Llvm => {
+            return Err(Error::Usage(format!(
+                "the {} backend does not produce llvm ir",
+                backend.name()
+            )))
         }
-    }?;
-
-    match optimization {
-        Optimization::O0 => base::compile(data),
-        Optimization::O1 => ir::compile(data),
-        Optimization::O2 => llvm::compile(data),
-    }?;
-
-    if optimization == Optimization::O0 || optimization == Optimization::O1 {
-        Command::new("nasm")
-            .args(["-g", "-f elf64", "code.s"])
-            .status()
-            .map_err(|e| format!("nasm: {e:?}"))?;
-
-        Command::new("ld")
-            .args(["-ocode", "code.o"])
-            .status()
-            .map_err(|e| format!("ld: {e:?}"))?;
-    } else {
-        Command::new("gcc")
-            .args(["-o", "code", "code.s"])
-            .status()
-            .map_err(|e| format!("gcc: {e:?}"))?;
-    }
-
-    let mut input = String::new();
-
-    stdin()
-        .lock()
-        .read_line(&mut input)
-        .map_err(|_| "Error reading string".to_string())?;
-
-    let input = if input.ends_with('\n') {
-        format!("{}\0", &input[0..input.len() - 1])
-    } else {
-        input
+        Emit::Obj => unreachable!("object files are written above"),
+    }
+
+    f.flush().map_err(Error::io(context))
+}
+
+fn signal_name(signal: i32) -> &'static str {
+    match signal {
+        1 => "SIGHUP",
+        2 => "SIGINT",
+        4 => "SIGILL",
+        6 => "SIGABRT",
+        7 => "SIGBUS",
+        8 => "SIGFPE",
+        9 => "SIGKILL",
+        11 => "SIGSEGV",
+        13 => "SIGPIPE",
+        15 => "SIGTERM",
+        _ => "unknown signal",
+    }
+}
+
+/// Exit code of the compiled program, or a runtime error when it was killed
+/// by a signal.
+fn exit_code(name: &str, status: ExitStatus) -> Result<ExitCode, Error> {
+    if let Some(code) = status.code() {
+        return Ok(ExitCode::from(code as u8));
+    }
+
+    match status.signal() {
+        Some(11) => Err(Error::Runtime(format!(
+            "{name} was killed by signal 11 (SIGSEGV), most likely an access out of the tape"
+        ))),
+        Some(signal) => Err(Error::Runtime(format!(
+            "{name} was killed by signal {signal} ({})",
+            signal_name(signal)
+        ))),
+        None => Err(Error::Runtime(format!("{name} stopped with {status}"))),
+    }
+}
+
+fn open(path: &Path) -> Result<File, Error> {
+    File::open(path).map_err(Error::io(path.display().to_string()))
+}
+
+fn open_input(input: &Input) -> Result<Box<dyn Read + Send>, Error> {
+    Ok(match input {
+        Input::Inherit => Box::new(stdin()),
+        Input::File(path) => Box::new(open(path)?),
+        Input::String(text) => Box::new(Cursor::new(text.clone().into_bytes())),
+    })
+}
+
+/// Runs `ast` with an interpreting backend, on the input of the program.
+fn interpret(cli: &Cli, backend: &dyn Backend, ast: Ast) -> Result<ExitCode, Error> {
+    let marker = Cursor::new(Vec::from_iter(cli.eof_marker));
+    let mut input = open_input(&cli.input)?.chain(marker);
+    let mut output = BufWriter::new(stdout().lock());
+
+    backend.execute(ast, &cli.options, &mut input, &mut output)?;
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Steps through `ast` in the debugger, which takes its commands from stdin
+/// and shares it with the program unless an input is given.
+fn debug(cli: &Cli, data: &str, ast: &Ast) -> Result<ExitCode, Error> {
+    let mut input = match cli.input {
+        Input::Inherit => None,
+        _ => Some(open_input(&cli.input)?),
     };
+    let input = input.as_mut().map(|input| input as &mut dyn Read);
+
+    interp::debug::run(
+        ast,
+        data,
+        &cli.options,
+        input,
+        &mut stdin().lock(),
+        &mut stdout().lock(),
+    )?;
+
+    Ok(ExitCode::SUCCESS)
+}
 
-    let mut cmd = Command::new("./code")
-        .stdin(Stdio::piped())
+fn run(executable: &Path, input: &Input, eof_marker: Option<u8>) -> Result<ExitCode, Error> {
+    let name = executable.display().to_string();
+
+    let child_stdin = match (input, eof_marker) {
+        (Input::Inherit, None) => Stdio::inherit(),
+        (Input::File(path), None) => Stdio::from(open(path)?),
+        _ => Stdio::piped(),
+    };
+
+    let mut source = open_input(input)?;
+
+    let mut child = Command::new(executable)
+        .stdin(child_stdin)
         .spawn()
-        .map_err(|e| format!("spawn: {e:?}"))?;
+        .map_err(|e| Error::Runtime(format!("cannot run {name}: {e}")))?;
+
+    // input that cannot be handed over as is gets fed from another thread, so
+    // that the program can write its output while reading
+    let feeder = child.stdin.take().map(|mut pipe| {
+        thread::spawn(move || -> io::Result<()> {
+            io::copy(&mut source, &mut pipe)?;
+            if let Some(byte) = eof_marker {
+                pipe.write_all(&[byte])?;
+            }
+            Ok(())
+        })
+    });
+
+    let status = child
+        .wait()
+        .map_err(|e| Error::Runtime(format!("{name}: {e}")))?;
 
-    if let Some(mut stdin) = cmd.stdin.take() {
-        stdin
-            .write_all(input.as_bytes())
-            .map_err(|_| "Error while passing stdin".to_string())?;
+    // a feeder reading our own stdin may wait forever for input that the
+    // program will never ask for, so it is left behind
+    if let (Some(feeder), Input::File(_) | Input::String(_)) = (feeder, input) {
+        match feeder.join().expect("input feeder panicked") {
+            Err(e) if e.kind() != ErrorKind::BrokenPipe => {
+                return Err(Error::io(format!("stdin of {name}"))(e))
+            }
+            _ => {}
+        }
     }
 
-    cmd.wait().map_err(|e| format!("exec: {e:?}"))?;
+    exit_code(&name, status)
+}
 
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        os::unix::process::ExitStatusExt,
+        process::{ExitCode, ExitStatus},
+    };
+
+    use frontend::Error;
+
+    use crate::{cli, execute, exit_code, temp::TempDir, Parsed};
+
+    #[test]
+    fn emit_writes_to_the_output() {
+        let dir = TempDir::new(false).unwrap();
+        let path = dir.path().join("code.txt");
+        let args = [
+            "emit",
+            "--emit",
+            "ast",
+            "-o",
+            path.to_str().unwrap(),
+            "-e",
+            "+.",
+        ];
+
+        let Ok(Parsed::Cli(cli)) = cli::parse(args.map(String::from)) else {
+            panic!("{args:?} did not parse");
+        };
+        execute(&cli, "+.").unwrap();
+
+        assert_eq!(fs::read_to_string(path).unwrap(), "Plus 1:1\nDot 1:2\n");
+    }
+
+    #[test]
+    fn exit_codes_are_passed_on() {
+        for code in [0, 1, 42, 255] {
+            let status = ExitStatus::from_raw(code << 8);
+            assert_eq!(
+                exit_code("./code", status).unwrap(),
+                ExitCode::from(code as u8)
+            );
+        }
+    }
+
+    #[test]
+    fn signals_are_runtime_errors() {
+        let message = |signal| match exit_code("./code", ExitStatus::from_raw(signal)) {
+            Err(Error::Runtime(message)) => message,
+            other => panic!("signal {signal} gave {other:?}"),
+        };
+
+        assert_eq!(
+            message(11),
+            "./code was killed by signal 11 (SIGSEGV), most likely an access out of the tape"
+        );
+        assert_eq!(message(6), "./code was killed by signal 6 (SIGABRT)");
+        assert_eq!(
+            message(31),
+            "./code was killed by signal 31 (unknown signal)"
+        );
+    }
 }
@@ -0,0 +1,27 @@
+use base::BaseBackend;
+use frontend::Backend;
+use interp::InterpBackend;
+use ir::IrBackend;
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+use jit::JitBackend;
+use llvm::LlvmBackend;
+use vm::VmBackend;
+
+/// Every backend selectable from the command line.
+pub const BACKENDS: &[&dyn Backend] = &[
+    &BaseBackend,
+    &IrBackend,
+    &LlvmBackend,
+    &InterpBackend,
+    &VmBackend,
+    // the jit generates x86-64 code and maps it with Linux system calls
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    &JitBackend,
+];
+
+pub fn find(name: &str) -> Option<&'static dyn Backend> {
+    BACKENDS
+        .iter()
+        .copied()
+        .find(|backend| backend.name() == name)
+}
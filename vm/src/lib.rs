@@ -0,0 +1,343 @@
+pub mod op;
+
+use std::{
+    io::{ErrorKind, Read, Write},
+    path::Path,
+};
+
+use frontend::{Ast, Backend, CellWidth, Eof, Error, Options};
+use op::{compile, Op, Word};
+
+/// Size of the chunks input is read by and output is written by.
+const BUFFER: usize = 8192;
+
+struct Machine<'a, C> {
+    options: &'a Options,
+    tape: Vec<C>,
+    head: usize,
+    input: &'a mut dyn Read,
+    output: &'a mut dyn Write,
+    read: Vec<u8>,
+    /// Position of the next byte of `read` to hand out.
+    next: usize,
+    written: Vec<u8>,
+}
+
+impl<C: Word> Machine<'_, C> {
+    fn execute(&mut self, ops: &[Op<C>]) -> Result<(), Error> {
+        let mut pc = 0;
+
+        while let Some(op) = ops.get(pc) {
+            match *op {
+                Op::Add(a) => self.tape[self.head] = self.tape[self.head].add(a),
+                Op::Set(a) => self.tape[self.head] = a,
+                Op::Move(s) => self.head = self.position(s)?,
+                Op::AddMove(a, s) => {
+                    self.tape[self.head] = self.tape[self.head].add(a);
+                    self.head = self.position(s)?;
+                }
+                Op::MulAdd(offset, factor) => {
+                    let cell = self.tape[self.head];
+                    if cell != C::default() {
+                        let index = self.position(offset)?;
+                        self.tape[index] = self.tape[index].add(cell.mul(factor));
+                    }
+                }
+                Op::Scan(s) => {
+                    while self.tape[self.head] != C::default() {
+                        self.head = self.position(s)?;
+                    }
+                }
+                Op::JumpZero(target) if self.tape[self.head] == C::default() => {
+                    pc = target as usize;
+                    continue;
+                }
+                Op::JumpNonZero(target) if self.tape[self.head] != C::default() => {
+                    pc = target as usize;
+                    continue;
+                }
+                Op::JumpZero(_) | Op::JumpNonZero(_) => {}
+                Op::In => self.read()?,
+                Op::Out => self.write()?,
+            }
+
+            pc += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Index of the cell `offset` cells away from the head, following the
+    /// bounds mode of the options like the interpreter does.
+    fn position(&mut self, offset: i32) -> Result<usize, Error> {
+        interp::position(&mut self.tape, self.head, offset as isize, self.options)
+    }
+
+    fn read(&mut self) -> Result<(), Error> {
+        if self.next == self.read.len() && !self.refill()? {
+            let cell = &mut self.tape[self.head];
+            match self.options.eof {
+                Eof::Zero => *cell = C::default(),
+                Eof::MinusOne => *cell = C::wrap(-1),
+                Eof::Unchanged => {}
+            }
+            return Ok(());
+        }
+
+        self.tape[self.head] = C::from_byte(self.read[self.next]);
+        self.next += 1;
+
+        Ok(())
+    }
+
+    /// Reads the next chunk of input, returning false at its end.
+    fn refill(&mut self) -> Result<bool, Error> {
+        // an interactive program shows its prompt before waiting for input
+        self.flush()?;
+
+        self.read.resize(BUFFER, 0);
+        loop {
+            match self.input.read(&mut self.read) {
+                Ok(n) => {
+                    self.read.truncate(n);
+                    self.next = 0;
+                    return Ok(n > 0);
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(Error::io("stdin")(e)),
+            }
+        }
+    }
+
+    fn write(&mut self) -> Result<(), Error> {
+        self.written.push(self.tape[self.head].to_byte());
+
+        if self.options.unbuffered || self.written.len() == BUFFER {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.output
+            .write_all(&self.written)
+            .and_then(|_| self.output.flush())
+            .map_err(Error::io("stdout"))?;
+        self.written.clear();
+
+        Ok(())
+    }
+}
+
+fn execute<C: Word>(
+    ast: Ast,
+    options: &Options,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<(), Error> {
+    let ops = compile::<C>(&ir::lower(ast, options));
+
+    let mut machine = Machine {
+        options,
+        tape: vec![C::default(); options.tape_size],
+        head: options.tape_origin,
+        input,
+        output,
+        read: Vec::with_capacity(BUFFER),
+        next: 0,
+        written: Vec::with_capacity(BUFFER),
+    };
+
+    let result = machine.execute(&ops);
+    // what was written before an error is still shown
+    machine.flush()?;
+
+    result
+}
+
+/// Runs the optimized IR of `ast` with the tape, cells and end of input
+/// behaviour of `options`, reading `,` from `input` and writing `.` to
+/// `output`.
+pub fn run(
+    ast: Ast,
+    options: &Options,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<(), Error> {
+    if options.unbounded {
+        return Err(Error::Usage(
+            "the vm backend does not support unbounded cells".into(),
+        ));
+    }
+
+    match options.cell_width {
+        CellWidth::Bits8 => execute::<u8>(ast, options, input, output),
+        CellWidth::Bits16 => execute::<u16>(ast, options, input, output),
+        CellWidth::Bits32 => execute::<u32>(ast, options, input, output),
+        CellWidth::Bits64 => execute::<u64>(ast, options, input, output),
+    }
+}
+
+/// Bytecode VM running the optimized IR in process, for machines without
+/// an assembler and as a second way to run what the optimizer produces.
+pub struct VmBackend;
+
+impl VmBackend {
+    fn no_code(&self) -> Error {
+        Error::Usage("the vm backend runs programs without generating code".into())
+    }
+}
+
+impl Backend for VmBackend {
+    fn name(&self) -> &'static str {
+        "vm"
+    }
+
+    fn extension(&self) -> &'static str {
+        ""
+    }
+
+    fn emit(&self, _: Ast, _: &Options, _: &mut dyn Write) -> Result<(), Error> {
+        Err(self.no_code())
+    }
+
+    fn assemble(&self, _: &Path, _: &Path) -> Result<(), Error> {
+        Err(self.no_code())
+    }
+
+    fn link(&self, _: &Path, _: &Path) -> Result<(), Error> {
+        Err(self.no_code())
+    }
+
+    fn interprets(&self) -> bool {
+        true
+    }
+
+    fn execute(
+        &self,
+        ast: Ast,
+        options: &Options,
+        input: &mut dyn Read,
+        output: &mut dyn Write,
+    ) -> Result<(), Error> {
+        run(ast, options, input, output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use frontend::{parse_source, Bounds, Eof, Error, OptLevel, Options};
+
+    use crate::{
+        op::{compile, Op},
+        run,
+    };
+
+    fn output(program: &str, input: &str, options: &Options) -> Result<Vec<u8>, Error> {
+        let ast = parse_source(program).unwrap();
+        let mut out = vec![];
+        run(ast, options, &mut input.as_bytes(), &mut out)?;
+
+        Ok(out)
+    }
+
+    #[test]
+    fn loops_become_superinstructions() {
+        let ast = parse_source(",[->++>+<<]>>[>]").unwrap();
+        let options = Options {
+            opt_level: OptLevel::O1,
+            ..Options::default()
+        };
+        let ops = compile::<u8>(&ir::lower(ast, &options));
+
+        assert_eq!(
+            ops,
+            [
+                Op::In,
+                Op::MulAdd(1, 2),
+                Op::MulAdd(2, 1),
+                Op::Set(0),
+                Op::Move(2),
+                Op::Scan(1),
+            ]
+        );
+    }
+
+    /// Every optimization level runs like the reference interpreter.
+    #[test]
+    fn optimizations_agree_with_the_interpreter() {
+        let programs = [
+            // multiplying a cell read from the input
+            (">,.+[-<+>]<.", "\x05"),
+            // a loop nested in one which is never entered
+            ("[][[][.]]+.", ""),
+            (
+                "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.",
+                "",
+            ),
+            (",[.,]", "echo"),
+            (">+[>,]<[<]>>[.>]", "abc"),
+            ("->+++[<[->>+<<]>>[-<<+>>]<-]<.", ""),
+            ("<", ""),
+        ];
+
+        for (program, input) in programs {
+            let reference = {
+                let ast = parse_source(program).unwrap();
+                let mut out = vec![];
+                let options = Options {
+                    bounds: Bounds::Trap,
+                    eof: Eof::Zero,
+                    ..Options::default()
+                };
+                interp::run(&ast, &options, &mut input.as_bytes(), &mut out)
+                    .map(|_| out)
+                    .map_err(|e| e.to_string())
+            };
+
+            for opt_level in [OptLevel::O0, OptLevel::O1, OptLevel::O2, OptLevel::O3] {
+                let options = Options {
+                    opt_level,
+                    bounds: Bounds::Trap,
+                    eof: Eof::Zero,
+                    ..Options::default()
+                };
+                let out = output(program, input, &options).map_err(|e| e.to_string());
+
+                assert_eq!(out, reference, "{program} at {opt_level:?}");
+            }
+        }
+    }
+
+    /// Offsets a tape size apart are the same cell of a wrapping tape.
+    #[test]
+    fn wrapping_tapes_agree_with_the_interpreter() {
+        let programs = [(">>>[-]<<<+.", ""), (">>>>,<<<<+.<<<<<<.", "a")];
+
+        for (program, input) in programs {
+            let options = |opt_level| Options {
+                opt_level,
+                bounds: Bounds::Wrap,
+                tape_size: 3,
+                ..Options::default()
+            };
+
+            let ast = parse_source(program).unwrap();
+            let mut reference = vec![];
+            interp::run(
+                &ast,
+                &options(OptLevel::O0),
+                &mut input.as_bytes(),
+                &mut reference,
+            )
+            .unwrap();
+
+            for opt_level in [OptLevel::O0, OptLevel::O1, OptLevel::O2, OptLevel::O3] {
+                let out = output(program, input, &options(opt_level)).unwrap();
+
+                assert_eq!(out, reference, "{program} at {opt_level:?}");
+            }
+        }
+    }
+}
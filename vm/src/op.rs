@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+use ir::optimizer::{IRExpr, IR};
+
+/// Value held by a tape cell, arithmetic wraps around at its width.
+pub trait Word: Copy + Default + Eq {
+    /// `value` modulo the width of the cell.
+    fn wrap(value: i64) -> Self;
+
+    fn add(self, other: Self) -> Self;
+
+    fn mul(self, other: Self) -> Self;
+
+    /// Value stored by `,`.
+    fn from_byte(byte: u8) -> Self;
+
+    /// Byte written by `.`.
+    fn to_byte(self) -> u8;
+}
+
+macro_rules! word {
+    ($($ty:ty),*) => {$(
+        impl Word for $ty {
+            fn wrap(value: i64) -> Self {
+                value as $ty
+            }
+
+            fn add(self, other: Self) -> Self {
+                self.wrapping_add(other)
+            }
+
+            fn mul(self, other: Self) -> Self {
+                self.wrapping_mul(other)
+            }
+
+            fn from_byte(byte: u8) -> Self {
+                byte as $ty
+            }
+
+            fn to_byte(self) -> u8 {
+                self as u8
+            }
+        }
+    )*};
+}
+
+word!(u8, u16, u32, u64);
+
+/// Instruction of the VM. Operands are held in the cell type and jump
+/// targets are indices into the program, so an instruction of 8 bit cells
+/// fits in 8 bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op<C> {
+    Add(C),
+    Set(C),
+    Move(i32),
+    /// `Add` followed by `Move`.
+    AddMove(C, i32),
+    /// Adds the cell times the factor to the cell at the offset, unless the
+    /// cell is zero. A loop counting its cell down to zero while adding to
+    /// other cells becomes these followed by `Set(0)`.
+    MulAdd(i32, C),
+    /// Moves by the offset until the head is on a zero cell.
+    Scan(i32),
+    /// Jumps to the target when the cell is zero.
+    JumpZero(u32),
+    /// Jumps to the target when the cell is not zero.
+    JumpNonZero(u32),
+    In,
+    Out,
+}
+
+/// Compiles `ir` into instructions, the matching ends of loops are found by
+/// their ids.
+pub fn compile<C: Word>(ir: &IR) -> Vec<Op<C>> {
+    let exprs = ir.iter().map(|expr| expr.node).collect::<Vec<_>>();
+
+    let mut ends = HashMap::new();
+    for (i, expr) in exprs.iter().enumerate() {
+        if let IRExpr::ConditionalEnd(id) = expr {
+            ends.insert(*id, i);
+        }
+    }
+
+    let mut ops = Vec::with_capacity(exprs.len());
+    let mut starts = HashMap::new();
+    let mut i = 0;
+
+    while i < exprs.len() {
+        match exprs[i] {
+            IRExpr::Update(a) => match exprs.get(i + 1) {
+                Some(IRExpr::Step(s) | IRExpr::CheckedStep(s)) => {
+                    ops.push(Op::AddMove(C::wrap(a), *s));
+                    i += 1;
+                }
+                _ => ops.push(Op::Add(C::wrap(a))),
+            },
+            IRExpr::Set(a) => ops.push(Op::Set(C::wrap(a))),
+            IRExpr::Step(s) | IRExpr::CheckedStep(s) => ops.push(Op::Move(s)),
+            IRExpr::Input => ops.push(Op::In),
+            IRExpr::Output => ops.push(Op::Out),
+            IRExpr::ConditionalStart(id) => {
+                let end = ends[&id];
+                let body = &exprs[i + 1..end];
+
+                if let [IRExpr::Step(s) | IRExpr::CheckedStep(s)] = body {
+                    ops.push(Op::Scan(*s));
+                    i = end + 1;
+                    continue;
+                }
+
+                if let Some(transfer) = transfer(body) {
+                    ops.extend(transfer);
+                    i = end + 1;
+                    continue;
+                }
+
+                // patched once the end of the loop is known
+                starts.insert(id, ops.len());
+                ops.push(Op::JumpZero(0));
+            }
+            IRExpr::ConditionalEnd(id) => {
+                let start = starts[&id];
+                ops.push(Op::JumpNonZero(start as u32 + 1));
+                ops[start] = Op::JumpZero(ops.len() as u32);
+            }
+        }
+
+        i += 1;
+    }
+
+    ops
+}
+
+/// Instructions of a loop body made of updates and steps which leaves the
+/// head where it started and counts its cell down by one, or up by one, on
+/// each iteration.
+fn transfer<C: Word>(body: &[IRExpr]) -> Option<Vec<Op<C>>> {
+    let mut changes = Vec::<(i32, i64)>::new();
+    let mut offset = 0;
+    let mut extent = (0, 0);
+
+    for expr in body {
+        match *expr {
+            IRExpr::Update(a) => match changes.iter_mut().find(|(o, _)| *o == offset) {
+                Some((_, factor)) => *factor = factor.wrapping_add(a),
+                None => changes.push((offset, a)),
+            },
+            IRExpr::Step(s) | IRExpr::CheckedStep(s) => {
+                offset += s;
+                extent = (extent.0.min(offset), extent.1.max(offset));
+            }
+            _ => return None,
+        }
+    }
+
+    let delta = match changes.iter().position(|(o, _)| *o == 0) {
+        Some(index) => changes.remove(index).1,
+        None => return None,
+    };
+
+    // the head must not go further than the cells it changes, where leaving
+    // the tape is noticed
+    let reached = changes
+        .iter()
+        .fold((0, 0), |(low, high), (o, _)| (low.min(*o), high.max(*o)));
+
+    if offset != 0 || !matches!(delta, 1 | -1) || reached != extent {
+        return None;
+    }
+
+    let mut ops = changes
+        .into_iter()
+        .map(|(offset, factor)| Op::MulAdd(offset, C::wrap(factor.wrapping_mul(-delta))))
+        .collect::<Vec<_>>();
+    ops.push(Op::Set(C::default()));
+
+    Some(ops)
+}